@@ -1,43 +1,97 @@
 //! Entry for the bot code
 
-use anyhow::{Context as _, Result};
+use anyhow::{bail, Context as _, Result};
 use clap::{CommandFactory, FromArgMatches};
 use deltachat::{
-    chat::{send_text_msg, Chat, ChatId},
+    chat::{send_msg, send_text_msg, Chat, ChatId},
     config::Config,
     constants::Chattype,
+    contact::{Contact, ContactId, Origin, VerifiedStatus},
     context::Context,
-    message::{Message, MsgId},
+    message::{Message, MsgId, Viewtype},
+    securejoin::get_securejoin_qr,
     stock_str::StockStrings,
     EventType, Events,
 };
 use itertools::Itertools;
 use log::{debug, error, info, warn};
-use std::{env, sync::Arc};
+use rand::Rng;
+use std::{
+    collections::HashMap,
+    env,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use tokio::sync::mpsc::{self, Receiver};
 
 use crate::{
     db::{Repository, DB},
-    parser::{Cli, Commands, Family},
-    rest_api::{create_hook, get_repository, remove_hook},
-    server::Server,
-    shared::{issue::IssueEvent, pr::PREvent, Repository as SharedRepo, WebhookEvent},
-    utils::{configure_from_env, send_text_to_all},
+    filter::{Filter, Subject},
+    health::HookHealth,
+    help,
+    message::{self, PrStatus},
+    parser::{
+        references, title_and_body, Cli, Commands, Family, FormatSubcommands, HealthSubcommands,
+        IssueSubcommands, NotificationSubcommands, PermissionSubcommands, PrSubcommands,
+        RepoSubcommands, TokenSubcommands, TriageSubcommands,
+    },
+    personal,
+    poller::Poller,
+    rest_api::{
+        add_assignees, add_labels, approve_pr, create_comment, create_issue, create_or_adopt_hook,
+        get_authenticated_user, get_check_runs, get_gists, get_hook, get_issue, get_mergeability,
+        get_pr, get_pr_files, get_repository, get_reviews, is_private, is_valid_login, list_issues,
+        list_prs, merge_pr, redeliver, remove_hook, remove_label, set_issue_state, update_hook,
+        Pages,
+    },
+    server::{attachment_url, remove_old_attachments, Server, ATTACHMENTS_DIR},
+    shared::{pr::PR, Repository as SharedRepo, WebhookEvent},
+    utils::{configure_from_env, send_list},
 };
 
 /// Internal representation of a git repository that can be subscribed to
 #[derive(Debug, Default)]
 pub struct GitRepository {
     pub name: String,
+    pub owner: String,
+    pub url: String,
     pub id: RepositoryId,
 }
 
-type RepositoryId = i64;
+impl GitRepository {
+    /// Name in the form `owner/name`
+    pub fn full_name(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+}
+
+type RepositoryId = usize;
+
+/// Time between two health checks of all webhooks
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Number of pull requests whose changed files are kept before the cache is emptied
+const PR_FILES_CACHE_SIZE: usize = 100;
+
+/// Time between two searches for attachments to delete
+const ATTACHMENT_CLEANUP_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Most references to issues and PRs in one message that get a summary
+const MAX_EXPANDED_REFERENCES: usize = 3;
 
 /// Github Bot state
 pub struct State {
     pub db: DB,
     pub ip: String,
+    /// Secret github uses to sign webhook deliveries
+    pub secret: Option<String>,
+    /// Address of the admin, who gets alerts and may use the admin commands
+    pub admin: Option<String>,
+    /// Public url of the server, attachments are only served if it is set
+    pub attachments_url: Option<String>,
+    /// Changed files of pull requests by repository, number and head commit
+    pub pr_files: Mutex<HashMap<(usize, usize, String), Vec<String>>>,
 }
 
 /// Github Bot
@@ -45,6 +99,7 @@ pub struct Bot {
     dc_ctx: Context,
     hook_receiver: Option<Receiver<WebhookEvent>>,
     hook_server: Server,
+    poller: Poller,
     state: Arc<State>,
 }
 
@@ -68,7 +123,20 @@ impl Bot {
 
         let (tx, rx) = mpsc::channel(100);
 
-        let db = DB::new("file://bot.db").await;
+        let db_key = env::var("db_key")
+            .context("set db_key to a passphrase that encrypts the stored tokens and api keys")
+            .unwrap();
+        let db = DB::new("file://bot.db", &db_key).await;
+        db.seal_plaintext_secrets()
+            .await
+            .context("failed to encrypt the stored tokens and api keys")
+            .unwrap();
+        let secret = env::var("hook_secret").ok();
+        let attachments_url = env::var("attachments_url").ok();
+        let hook_server = Server::new(tx.clone(), secret.clone(), attachments_url.is_some())
+            .await
+            .context("failed to create the webhook server")
+            .unwrap();
 
         Self {
             dc_ctx: ctx,
@@ -84,8 +152,13 @@ impl Bot {
                     .unwrap()
                     .ip()
                     .to_string(),
+                secret,
+                admin: env::var("admin_addr").ok(),
+                attachments_url,
+                pr_files: Mutex::new(HashMap::new()),
             }),
-            hook_server: Server::new(tx),
+            hook_server,
+            poller: Poller::new(tx),
         }
     }
 
@@ -112,6 +185,9 @@ impl Bot {
         // start webhook-server
         self.hook_server.start();
 
+        // start polling repositories without webhook
+        self.poller.start(self.state.clone());
+
         info!("initiated webhook server (3/4)");
 
         // start webhook-handler
@@ -126,9 +202,217 @@ impl Bot {
             }
         });
         info!("initiated webhook handler (4/4)");
+
+        // check that the webhooks still match the subscriptions
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            for repo in state.db.get_repository_ids().await.unwrap_or_default() {
+                if let Err(e) = Self::fix_hook_drift(&state, repo).await {
+                    error!("{e}")
+                }
+            }
+        });
+
+        // periodically check the health of all webhooks
+        let state = self.state.clone();
+        let ctx = self.dc_ctx.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                for repo in state.db.get_repository_ids().await.unwrap_or_default() {
+                    if let Err(e) = Self::watch_hook_health(&ctx, &state, repo).await {
+                        error!("{e}")
+                    }
+                }
+            }
+        });
+        // delete attachments once github had enough time to show them
+        if self.state.attachments_url.is_some() {
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(ATTACHMENT_CLEANUP_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    if let Err(e) = remove_old_attachments().await {
+                        error!("{e}")
+                    }
+                }
+            });
+        }
+        // contacts verify the bot with this before they may approve and merge
+        match get_securejoin_qr(&self.dc_ctx, None).await {
+            Ok(qr) => info!("verify the bot by scanning a QR code with: {qr}"),
+            Err(e) => error!("{e}"),
+        }
         info!("successfully started bot! 🥳");
     }
 
+    /// Check the webhook of a repository and return its name together with the result
+    async fn hook_health(state: &State, repo: usize) -> Result<(String, HookHealth)> {
+        if state.db.is_polling(repo).await? {
+            bail!("this repository is polled and has no webhook");
+        }
+        let api_key = state
+            .db
+            .get_api_key(repo)
+            .await?
+            .context("no api key stored for this repository")?;
+        let owner = state.db.get_owner(repo).await?;
+        let name = state.db.get_name(repo).await?;
+        let hook_id = state.db.get_hook_id(repo).await?;
+        let expect_active = !state.db.get_subscribed_events(repo).await?.is_empty();
+        let health = HookHealth::check(&owner, &name, hook_id, &api_key, expect_active).await?;
+        Ok((format!("{owner}/{name}"), health))
+    }
+
+    /// Alert the admin chat when the webhook of a repository starts or stops failing
+    async fn watch_hook_health(ctx: &Context, state: &State, repo: usize) -> Result<()> {
+        if state.db.get_api_key(repo).await?.is_none() || state.db.is_polling(repo).await? {
+            return Ok(());
+        }
+        let (name, health) = Self::hook_health(state, repo).await?;
+        let failing = !health.is_healthy();
+        if failing == state.db.is_failing(repo).await? {
+            return Ok(());
+        }
+        state.db.set_failing(repo, failing).await?;
+        let report = health.report(&name);
+        warn!("{report}");
+        if let Some(admin_chat) = Self::admin_chat(ctx, state).await? {
+            send_text_msg(ctx, admin_chat, report).await?;
+        }
+        Ok(())
+    }
+
+    /// Chat which gets the alerts, the one the admin chose or else the 1:1 chat with the admin
+    async fn admin_chat(ctx: &Context, state: &State) -> Result<Option<ChatId>> {
+        let Some(admin) = &state.admin else {
+            return Ok(None);
+        };
+        if let Some(chat) = state.db.get_admin_chat(admin).await? {
+            return Ok(Some(chat));
+        }
+        let contact = Contact::create(ctx, "", admin).await?;
+        Ok(Some(ChatId::create_for_contact(ctx, contact).await?))
+    }
+
+    /// Refuse messages that don't prove who sent them
+    ///
+    /// Anyone can write any address into the `From:` header of an unencrypted mail,
+    /// only encrypted and signed messages are known to come from the contact.
+    fn require_encrypted(msg: &Message) -> Result<()> {
+        if !msg.get_showpadlock() {
+            bail!(
+                "This needs an end-to-end encrypted message, \
+                 so that nobody else can send it in your name"
+            );
+        }
+        Ok(())
+    }
+
+    /// Refuse messages that aren't encrypted or come from a contact that wasn't verified
+    ///
+    /// Verification by QR code makes sure the key of the contact isn't the one of an attacker.
+    async fn require_verified(ctx: &Context, msg: &Message) -> Result<()> {
+        Self::require_encrypted(msg)?;
+        let contact = Contact::get_by_id(ctx, msg.get_from_id()).await?;
+        if contact.is_verified(ctx).await? == VerifiedStatus::Unverified {
+            bail!(
+                "This needs a verified contact, scan the QR code of the bot \
+                 which its operator finds in the log"
+            );
+        }
+        Ok(())
+    }
+
+    /// Refuse messages that don't come from the admin set with `admin_addr`
+    async fn require_admin(ctx: &Context, state: &State, msg: &Message) -> Result<()> {
+        let admin = state.admin.as_deref().context(
+            "The bot has no admin, its operator can set one with the `admin_addr` variable",
+        )?;
+        let sender = Contact::get_by_id(ctx, msg.get_from_id()).await?;
+        if !sender.get_addr().eq_ignore_ascii_case(admin) {
+            bail!("Only the admin of the bot can do this");
+        }
+        Self::require_encrypted(msg)
+    }
+
+    /// Ask github to resend all failed deliveries of a repository
+    async fn redeliver_failed(state: &State, repo: usize) -> Result<usize> {
+        let (_, health) = Self::hook_health(state, repo).await?;
+        let api_key = state.db.get_api_key(repo).await?.unwrap_or_default();
+        let owner = state.db.get_owner(repo).await?;
+        let name = state.db.get_name(repo).await?;
+        for delivery in &health.failed {
+            redeliver(&owner, &name, health.hook.id, delivery.id, &api_key).await?;
+        }
+        Ok(health.failed.len())
+    }
+
+    /// Register the webhook of a repository for exactly the events its subscribers listen to
+    async fn sync_hook_events(state: &State, repo: usize) -> Result<()> {
+        if state.db.is_polling(repo).await? {
+            return Ok(());
+        }
+        let events = state.db.get_subscribed_events(repo).await?;
+        if state.db.get_events(repo).await? == events {
+            return Ok(());
+        }
+        let Some(api_key) = state.db.get_api_key(repo).await? else {
+            warn!("can't update webhook of repository {repo} without api key");
+            return Ok(());
+        };
+        let owner = state.db.get_owner(repo).await?;
+        let name = state.db.get_name(repo).await?;
+        let hook_id = state.db.get_hook_id(repo).await?;
+        update_hook(
+            &owner,
+            &name,
+            hook_id,
+            &api_key,
+            &state.ip,
+            &events,
+            state.secret.as_deref(),
+        )
+        .await?;
+        state.db.set_events(repo, &events).await?;
+        info!("updated webhook events of {owner}/{name} to {events:?}");
+        Ok(())
+    }
+
+    /// Report and fix a webhook whose events drifted from the subscriptions
+    async fn fix_hook_drift(state: &State, repo: usize) -> Result<()> {
+        let Some(api_key) = state.db.get_api_key(repo).await? else {
+            return Ok(());
+        };
+        if state.db.is_polling(repo).await? {
+            return Ok(());
+        }
+        let owner = state.db.get_owner(repo).await?;
+        let name = state.db.get_name(repo).await?;
+        let hook_id = state.db.get_hook_id(repo).await?;
+        let events = state.db.get_subscribed_events(repo).await?;
+        let hook = get_hook(&owner, &name, hook_id, &api_key).await?;
+        if hook.events_differ(&events) {
+            warn!(
+                "webhook of {owner}/{name} registered for {:?} but subscribed to {events:?}",
+                hook.events
+            );
+            update_hook(
+                &owner,
+                &name,
+                hook_id,
+                &api_key,
+                &state.ip,
+                &events,
+                state.secret.as_deref(),
+            )
+            .await?;
+        }
+        state.db.set_events(repo, &events).await?;
+        Ok(())
+    }
+
     /// Handle _all_ dc-events
     async fn dc_event_handler(ctx: &Context, state: Arc<State>, event: EventType) {
         match event {
@@ -172,107 +456,63 @@ impl Bot {
         }
 
         if let Some(text) = msg.get_text() {
+            // replies to notifications are posted as comments
+            if !text.starts_with("gh") {
+                if let Some(quote) = msg.quoted_message(ctx).await? {
+                    if let Some((repo, number)) = state.db.get_notification(quote.get_id()).await? {
+                        if let Err(err) =
+                            Self::comment(ctx, &state, chat_id, &msg, repo, number, &text).await
+                        {
+                            error!("{err}");
+                            send_text_msg(ctx, chat_id, err.to_string()).await?;
+                        }
+                        return Ok(());
+                    }
+                }
+            }
+
             // only react to messages with right keywoard
             if text.starts_with("gh") {
-                match <Cli as CommandFactory>::command().try_get_matches_from(text.split(' ')) {
+                let words = text.split(' ').collect::<Vec<_>>();
+                match <Cli as CommandFactory>::command().try_get_matches_from(&words) {
                     Ok(mut matches) => {
                         let res = <Cli as FromArgMatches>::from_arg_matches_mut(&mut matches)?;
-
-                        match &res.command {
-                            Commands::Subscribe { .. } => {
-                                info!("adding subscriber");
-                                state.db.add_subscriber(res.command, chat_id).await;
-                                send_text_msg(ctx, chat_id, "Added event listener".to_string())
-                                    .await?;
-                            }
-                            Commands::Unsubscribe { .. } => {
-                                info!("removing subscriber");
-                                state.db.remove_subscriber(res.command, chat_id).await;
-                                send_text_msg(ctx, chat_id, "Removed event listener".to_string())
-                                    .await?;
-                            }
-                            Commands::Repositories { repo_subcommands } => match repo_subcommands {
-                                crate::parser::RepoSubcommands::List => {
-                                    let repos = state.db.get_repository_ids().await?;
-                                    let text = if !repos.is_empty() {
-                                        format!(
-                                            "Available repositories:\n{}",
-                                            repos.iter().join("\n")
-                                        )
-                                    } else {
-                                        "No repositories have been added yet".to_string()
-                                    };
-                                    error!("{text}");
-                                    send_text_msg(ctx, chat_id, text).await?;
-                                }
-                                crate::parser::RepoSubcommands::Add {
-                                    owner,
-                                    repository,
-                                    api_key,
-                                } => match create_hook(owner, repository, api_key, &state.ip).await
-                                {
-                                    Ok(hook_id) => {
-                                        let SharedRepo { id, url, .. } =
-                                            get_repository(owner, repository, api_key).await?;
-                                        state
-                                            .db
-                                            .add_repository(Repository {
-                                                name: repository,
-                                                owner,
-                                                hook_id,
-                                                id,
-                                                url: &url,
-                                            })
-                                            .await?;
-                                        info!("Added new webhook for repository {repository}");
-                                        send_text_msg(
-                                            ctx,
-                                            chat_id,
-                                            "Successfully added webhook".to_string(),
-                                        )
-                                        .await?;
-                                    }
-                                    Err(err) => {
-                                        error!("{err}");
-                                        send_text_msg(ctx, chat_id, err.to_string()).await?;
-                                    }
-                                },
-                                crate::parser::RepoSubcommands::Remove {
-                                    repository,
-                                    api_key,
-                                } => {
-                                    let hook_id = state.db.get_hook_id(*repository).await?;
-                                    let owner = state.db.get_owner(*repository).await.unwrap();
-                                    let repo = state.db.get_name(*repository).await.unwrap();
-                                    match remove_hook(&owner, &repo, hook_id, api_key).await {
-                                        Ok(_) => {
-                                            info!("removed webhook for repo {repository}");
-                                            send_text_msg(
-                                                ctx,
-                                                chat_id,
-                                                "Successfully removed repository".to_string(),
-                                            )
-                                            .await?;
-                                        }
-                                        Err(err) => {
-                                            error!("{err}");
-                                            send_text_msg(ctx, chat_id, err.to_string()).await?;
-                                        }
-                                    }
-                                }
-                            },
+                        if let Err(err) =
+                            Self::handle_command(ctx, &state, chat_id, &msg, res.command).await
+                        {
+                            error!("{err}");
+                            send_text_msg(ctx, chat_id, err.to_string()).await?;
                         }
                     }
                     Err(err) => {
-                        send_text_msg(ctx, chat_id, err.to_string()).await.unwrap();
+                        let reply = help::error_reply(&words[1..], &err);
+                        send_text_msg(ctx, chat_id, reply).await?;
                     }
                 };
             } else {
-                if !chat_id.is_special() {
+                // mentions of issues and PRs of known repositories get a summary
+                let mut expanded = false;
+                for reference in references(&text).iter().take(MAX_EXPANDED_REFERENCES) {
+                    let Ok(repo) = state.db.resolve_repository(&reference.repo).await else {
+                        continue;
+                    };
+                    match Self::show(ctx, &state, chat_id, &msg, repo, reference.number).await {
+                        Ok(()) => expanded = true,
+                        Err(err) => warn!("can't expand {reference}: {err}"),
+                    }
+                }
+                if !expanded && !chat_id.is_special() {
                     let chat = Chat::load_from_db(ctx, chat_id).await?;
                     if let Chattype::Single = chat.typ {
-                        send_text_msg(ctx, chat_id, "Commands must start with gh".to_string())
-                            .await?;
+                        let contact = msg.get_from_id();
+                        let text = if state.db.is_introduced(contact).await? {
+                            "Commands must start with gh, send `gh help` for a list of them"
+                                .to_string()
+                        } else {
+                            state.db.set_introduced(contact).await?;
+                            help::introduction()
+                        };
+                        send_text_msg(ctx, chat_id, text).await?;
                     }
                 }
             }
@@ -281,67 +521,1143 @@ impl Bot {
         Ok(())
     }
 
-    /// Handle a parsed webhook-event
-    async fn handle_webhook(
-        state: Arc<State>,
+    /// Key for reading a repository in the name of the sender of a message
+    ///
+    /// The token of the contact comes first, so that nobody sees more than github shows them.
+    /// The api key of the repository is only used for public repositories.
+    async fn read_key(state: &State, repo: usize, msg: &Message) -> Result<String> {
+        if msg.get_showpadlock() {
+            if let Some(token) = state.db.get_token(msg.get_from_id()).await? {
+                return Ok(token);
+            }
+        }
+        if let Some(key) = state.db.get_api_key(repo).await? {
+            let owner = state.db.get_owner(repo).await?;
+            let name = state.db.get_name(repo).await?;
+            if !is_private(&owner, &name, &key).await? {
+                return Ok(key);
+            }
+        }
+        Self::contact_token(state, msg).await
+    }
+
+    /// Github login given on the command line, where `me` is the contact's own
+    async fn resolve_login(state: &State, contact: ContactId, login: String) -> Result<String> {
+        if login != "me" {
+            return Ok(login);
+        }
+        state
+            .db
+            .get_login(contact)
+            .await?
+            .context("You aren't linked to a github login yet, see `gh link`")
+    }
+
+    /// Send a summary of an issue or PR
+    async fn show(
         ctx: &Context,
-        event: WebhookEvent,
-    ) -> anyhow::Result<()> {
-        info!("Handling webhook event {}", event);
-        match event {
-            WebhookEvent::Issue(IssueEvent {
-                sender,
-                action,
-                repository,
-                issue,
-            }) => {
-                let subs = state
+        state: &State,
+        chat_id: ChatId,
+        msg: &Message,
+        repo: usize,
+        number: usize,
+    ) -> Result<()> {
+        let key = Self::read_key(state, repo, msg).await?;
+        let owner = state.db.get_owner(repo).await?;
+        let name = state.db.get_name(repo).await?;
+        let item = get_issue(&owner, &name, number, &key).await?;
+        let status = if item.is_pull_request() {
+            let pr = get_pr(&owner, &name, number, &key).await?;
+            let reviews = get_reviews(&owner, &name, number, &key).await?;
+            let checks = get_check_runs(&owner, &name, &pr.head.sha, &key).await?;
+            Some(PrStatus {
+                pr,
+                reviews,
+                checks,
+            })
+        } else {
+            None
+        };
+        let text = message::summary(&format!("{owner}/{name}"), &item, status.as_ref());
+        send_text_msg(ctx, chat_id, text).await?;
+        Ok(())
+    }
+
+    /// Post a reply to a notification as comment on its issue or PR
+    async fn comment(
+        ctx: &Context,
+        state: &State,
+        chat_id: ChatId,
+        msg: &Message,
+        repo: usize,
+        number: usize,
+        text: &str,
+    ) -> Result<()> {
+        let token = Self::contact_token(state, msg).await?;
+        let owner = state.db.get_owner(repo).await?;
+        let name = state.db.get_name(repo).await?;
+        let comment = create_comment(&owner, &name, number, &token, text).await?;
+        send_text_msg(
+            ctx,
+            chat_id,
+            format!("Commented on {owner}/{name}#{number}: {}", comment.html_url),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Execute a parsed command, errors are reported back to the chat
+    async fn handle_command(
+        ctx: &Context,
+        state: &State,
+        chat_id: ChatId,
+        msg: &Message,
+        command: Commands,
+    ) -> Result<()> {
+        match command {
+            Commands::Help { command } => {
+                let words = command.iter().map(String::as_str).collect::<Vec<_>>();
+                send_text_msg(ctx, chat_id, help::help(&words)).await?;
+            }
+            Commands::Subscribe {
+                repo,
+                filter,
+                family,
+            } => {
+                info!("adding subscriber");
+                let repo = state.db.resolve_repository(&repo).await?;
+                let (mut added, mut changed, mut present) = (vec![], vec![], vec![]);
+                for list in family.lists() {
+                    let new = state.db.add_subscriber(repo, &list, chat_id).await?;
+                    let filtered = state.db.set_filter(repo, &list, chat_id, &filter).await?;
+                    let description = describe_subscription(&list, &filter);
+                    if new {
+                        added.push(description);
+                    } else if filtered {
+                        changed.push(description);
+                    } else {
+                        present.push(description);
+                    }
+                }
+                if let Err(err) = Self::sync_hook_events(state, repo).await {
+                    error!("{err}");
+                }
+                let text = change_report(&[
+                    ("Added", added.as_slice()),
+                    ("Changed filter", changed.as_slice()),
+                    ("Already subscribed", present.as_slice()),
+                ]);
+                send_text_msg(ctx, chat_id, text).await?;
+            }
+            Commands::Unsubscribe { repo, family } => {
+                info!("removing subscriber");
+                let every_repo = repo.is_none();
+                let repos = match repo {
+                    Some(repo) => vec![state.db.resolve_repository(&repo).await?],
+                    None => state.db.get_repository_ids().await?,
+                };
+                let (mut removed, mut missing) = (vec![], vec![]);
+                for repo in repos {
+                    // name the repository when removing from several at once
+                    let prefix = if every_repo {
+                        format!("{}: ", state.db.get_name(repo).await?)
+                    } else {
+                        String::new()
+                    };
+                    if let Family::All = family {
+                        let lists = state.db.remove_all_subscriptions(repo, chat_id).await?;
+                        removed.extend(
+                            lists
+                                .iter()
+                                .map(|list| format!("{prefix}{}", describe_list(list))),
+                        );
+                    } else {
+                        for list in family.lists() {
+                            if state.db.remove_subscriber(repo, &list, chat_id).await? {
+                                removed.push(format!("{prefix}{}", describe_list(&list)));
+                            } else if !every_repo {
+                                missing.push(describe_list(&list));
+                            }
+                        }
+                    }
+                    if let Err(err) = Self::sync_hook_events(state, repo).await {
+                        error!("{err}");
+                    }
+                }
+                let mut text = change_report(&[
+                    ("Removed", removed.as_slice()),
+                    ("Not subscribed", missing.as_slice()),
+                ]);
+                if text.is_empty() {
+                    text = "No subscriptions to remove".to_string();
+                }
+                send_text_msg(ctx, chat_id, text).await?;
+            }
+            Commands::Subscriptions => {
+                let subscriptions = state
                     .db
-                    .get_subscribers(
-                        repository.id,
-                        Family::Issue {
-                            issue_action: action,
-                        },
-                    )
-                    .await
-                    .unwrap();
-                send_text_to_all(
-                    &subs,
-                    &format!(
-                        "User {} triggered event `{action}` on issue {}",
-                        sender.login, issue.title
+                    .get_subscriptions(chat_id)
+                    .await?
+                    .into_iter()
+                    .group_by(|(repo, _)| *repo)
+                    .into_iter()
+                    .map(|(repo, lists)| (repo, lists.map(|(_, list)| list).collect::<Vec<_>>()))
+                    .collect::<Vec<_>>();
+                let text = if subscriptions.is_empty() {
+                    "This chat has no subscriptions".to_string()
+                } else {
+                    let mut lines = vec![];
+                    for (repo, lists) in subscriptions {
+                        let owner = state.db.get_owner(repo).await?;
+                        let name = state.db.get_name(repo).await?;
+                        let mut descriptions = vec![];
+                        for list in lists {
+                            let filter = state.db.get_filter(repo, &list, chat_id).await?;
+                            descriptions.push(describe_subscription(&list, &filter));
+                        }
+                        lines.push(format!("{owner}/{name}: {}", descriptions.join(", ")));
+                    }
+                    format!("Subscriptions of this chat:\n{}", lines.join("\n"))
+                };
+                send_text_msg(ctx, chat_id, text).await?;
+            }
+            Commands::Repositories { repo_subcommands } => {
+                Self::handle_repo_command(ctx, state, chat_id, repo_subcommands).await?
+            }
+            Commands::Health { health_subcommands } => {
+                Self::handle_health_command(ctx, state, chat_id, msg, health_subcommands).await?
+            }
+            Commands::Format { format_subcommands } => {
+                Self::handle_format_command(ctx, state, chat_id, format_subcommands).await?
+            }
+            Commands::Issue { issue_subcommands } => {
+                Self::handle_issue_command(ctx, state, chat_id, msg, issue_subcommands).await?
+            }
+            Commands::Pr { pr_subcommands } => {
+                Self::handle_pr_command(ctx, state, chat_id, msg, pr_subcommands).await?
+            }
+            Commands::Issues {
+                repo,
+                labels,
+                assignee,
+            } => {
+                let repo = state.db.resolve_repository(&repo).await?;
+                let contact = msg.get_from_id();
+                let key = Self::read_key(state, repo, msg).await?;
+                let assignee = match assignee {
+                    Some(login) => Some(Self::resolve_login(state, contact, login).await?),
+                    None => None,
+                };
+                let owner = state.db.get_owner(repo).await?;
+                let name = state.db.get_name(repo).await?;
+                let issues = list_issues(&owner, &name, &key, &labels, assignee.as_deref()).await?;
+                if issues.items.is_empty() {
+                    send_text_msg(ctx, chat_id, format!("No open issues in {owner}/{name}"))
+                        .await?;
+                } else {
+                    let lines = issues
+                        .items
+                        .iter()
+                        .map(message::issue_line)
+                        .collect::<Vec<_>>();
+                    let heading = format!(
+                        "Open issues in {owner}/{name}{}:",
+                        truncation_note(issues.truncated, issues.fetched, "issues and PRs")
+                    );
+                    send_list(ctx, chat_id, &heading, &lines).await?;
+                }
+            }
+            Commands::Prs {
+                repo,
+                review_requested,
+            } => {
+                let repo = state.db.resolve_repository(&repo).await?;
+                let contact = msg.get_from_id();
+                let key = Self::read_key(state, repo, msg).await?;
+                let reviewer = match review_requested {
+                    Some(login) => Some(Self::resolve_login(state, contact, login).await?),
+                    None => None,
+                };
+                let owner = state.db.get_owner(repo).await?;
+                let name = state.db.get_name(repo).await?;
+                let Pages {
+                    items: mut prs,
+                    truncated,
+                    fetched,
+                    ..
+                } = list_prs(&owner, &name, &key).await?;
+                if let Some(reviewer) = &reviewer {
+                    prs.retain(|pr| {
+                        pr.requested_reviewers
+                            .iter()
+                            .any(|user| user.login.eq_ignore_ascii_case(reviewer))
+                    });
+                }
+                if prs.is_empty() {
+                    send_text_msg(ctx, chat_id, format!("No open PRs in {owner}/{name}")).await?;
+                } else {
+                    let lines = prs.iter().map(message::pr_line).collect::<Vec<_>>();
+                    let heading = format!(
+                        "Open PRs in {owner}/{name}{}:",
+                        truncation_note(truncated, fetched, "PRs")
+                    );
+                    send_list(ctx, chat_id, &heading, &lines).await?;
+                }
+            }
+            Commands::Show { reference } => {
+                let repo = state.db.resolve_repository(&reference.repo).await?;
+                Self::show(ctx, state, chat_id, msg, repo, reference.number).await?
+            }
+            Commands::Permissions {
+                permission_subcommands,
+            } => {
+                Self::handle_permission_command(ctx, state, chat_id, msg, permission_subcommands)
+                    .await?
+            }
+            Commands::Link { login } => {
+                Self::require_encrypted(msg)?;
+                let text = Self::link(state, msg.get_from_id(), &login).await?;
+                send_text_msg(ctx, chat_id, text).await?;
+            }
+            Commands::Unlink => {
+                Self::require_encrypted(msg)?;
+                state.db.remove_login(msg.get_from_id()).await?;
+                send_text_msg(
+                    ctx,
+                    chat_id,
+                    "Removed the link to your github login".to_string(),
+                )
+                .await?;
+            }
+            Commands::Notifications {
+                notification_subcommands,
+            } => {
+                Self::require_encrypted(msg)?;
+                let contact = msg.get_from_id();
+                let enabled = notification_subcommands == NotificationSubcommands::On;
+                let text = match state.db.get_login(contact).await? {
+                    None if enabled => bail!("Link your github login first, see `gh link`"),
+                    Some(login) if enabled => format!(
+                        "You will get review requests, assignments and mentions of {login} \
+                         in a 1:1 chat with the bot"
                     ),
+                    _ => "Stopped your personal notifications".to_string(),
+                };
+                state.db.set_personal(contact, enabled).await?;
+                for repo in state.db.get_repository_ids().await? {
+                    if let Err(err) = Self::sync_hook_events(state, repo).await {
+                        error!("{err}");
+                    }
+                }
+                send_text_msg(ctx, chat_id, text).await?;
+            }
+            Commands::Whoami => {
+                let text = match state.db.get_login(msg.get_from_id()).await? {
+                    Some(login) => format!("You are linked to {login}"),
+                    None => "You aren't linked to a github login, see `gh link`".to_string(),
+                };
+                send_text_msg(ctx, chat_id, text).await?;
+            }
+            Commands::Token { token_subcommands } => {
+                Self::require_encrypted(msg)?;
+                let contact = msg.get_from_id();
+                Self::handle_token_command(ctx, state, chat_id, contact, token_subcommands).await?
+            }
+        }
+        Ok(())
+    }
+
+    /// Work with issues in the name of the contact who sent the command
+    async fn handle_issue_command(
+        ctx: &Context,
+        state: &State,
+        chat_id: ChatId,
+        msg: &Message,
+        command: IssueSubcommands,
+    ) -> Result<()> {
+        let token = Self::contact_token(state, msg).await?;
+        match command {
+            IssueSubcommands::New {
+                repo,
+                labels,
+                assignees,
+                text,
+            } => {
+                let repo = state.db.resolve_repository(&repo).await?;
+                let owner = state.db.get_owner(repo).await?;
+                let name = state.db.get_name(repo).await?;
+                let (title, mut body) = title_and_body(&text);
+                let mut note = "";
+                match Self::publish_attachment(ctx, state, msg).await? {
+                    Some(attachment) => {
+                        body = format!("{body}\n\n{attachment}").trim().to_string();
+                    }
+                    None if msg.get_file(ctx).is_some() => {
+                        note = "\nThe attachment was left out, this bot doesn't serve attachments";
+                    }
+                    None => {}
+                }
+                let issue =
+                    create_issue(&owner, &name, &token, &title, &body, &labels, &assignees).await?;
+                send_text_msg(
+                    ctx,
+                    chat_id,
+                    format!(
+                        "Opened {owner}/{name}#{}: {}{note}",
+                        issue.number, issue.html_url
+                    ),
+                )
+                .await?;
+            }
+            IssueSubcommands::Triage(command) => {
+                Self::triage(ctx, state, chat_id, &token, command, false).await?
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_pr_command(
+        ctx: &Context,
+        state: &State,
+        chat_id: ChatId,
+        msg: &Message,
+        command: PrSubcommands,
+    ) -> Result<()> {
+        let token = Self::contact_token(state, msg).await?;
+        match command {
+            PrSubcommands::Triage(command) => {
+                Self::triage(ctx, state, chat_id, &token, command, true).await?
+            }
+            PrSubcommands::Approve {
+                repo,
+                number,
+                comment,
+            } => {
+                let repo = state.db.resolve_repository(&repo).await?;
+                let (owner, name) = Self::merge_target(ctx, state, msg, repo).await?;
+                approve_pr(&owner, &name, number, &token, &comment.join(" ")).await?;
+                send_text_msg(ctx, chat_id, format!("Approved {owner}/{name}#{number}")).await?;
+            }
+            PrSubcommands::Merge {
+                repo,
+                number,
+                squash,
+                rebase,
+            } => {
+                let repo = state.db.resolve_repository(&repo).await?;
+                let (owner, name) = Self::merge_target(ctx, state, msg, repo).await?;
+                let pr = get_mergeability(&owner, &name, number, &token).await?;
+                let reference = format!("{owner}/{name}#{number}");
+                if pr.merged {
+                    bail!("{reference} is already merged");
+                }
+                if pr.state != "open" {
+                    bail!("{reference} is closed");
+                }
+                if pr.draft {
+                    bail!("{reference} is a draft");
+                }
+                match (pr.mergeable, pr.mergeable_state.as_str()) {
+                    (None, _) => {
+                        bail!(
+                            "Github is still checking whether {reference} can be merged, \
+                             try again in a moment"
+                        )
+                    }
+                    (Some(false), _) | (_, "dirty") => {
+                        bail!("{reference} has conflicts with its base branch")
+                    }
+                    (_, "blocked") => {
+                        let checks = get_check_runs(&owner, &name, &pr.head.sha, &token).await?;
+                        let failed = checks
+                            .iter()
+                            .filter(|check| {
+                                check.status != "completed"
+                                    || !matches!(
+                                        check.conclusion.as_deref(),
+                                        Some("success" | "neutral" | "skipped")
+                                    )
+                            })
+                            .map(|check| &check.name)
+                            .join(", ");
+                        if failed.is_empty() {
+                            bail!(
+                                "{reference} is blocked by branch protection, e.g. missing reviews"
+                            )
+                        }
+                        bail!("{reference} is blocked by failed or pending checks: {failed}")
+                    }
+                    _ => {}
+                }
+                let method = match (squash, rebase) {
+                    (true, _) => "squash",
+                    (_, true) => "rebase",
+                    _ => "merge",
+                };
+                let sha = merge_pr(&owner, &name, number, &token, method, &pr.head.sha).await?;
+                send_text_msg(
                     ctx,
+                    chat_id,
+                    format!("Merged {reference} ({})", sha.get(..7).unwrap_or(&sha)),
                 )
                 .await?;
             }
-            WebhookEvent::PR(PREvent {
-                action,
-                sender,
+        }
+        Ok(())
+    }
+
+    /// Owner and name of a repository if the contact may approve and merge its PRs
+    async fn merge_target(
+        ctx: &Context,
+        state: &State,
+        msg: &Message,
+        repo: usize,
+    ) -> Result<(String, String)> {
+        Self::require_verified(ctx, msg).await?;
+        let owner = state.db.get_owner(repo).await?;
+        let name = state.db.get_name(repo).await?;
+        if !state.db.may_merge(msg.get_from_id(), repo).await? {
+            bail!(
+                "You aren't allowed to approve and merge PRs of {owner}/{name}, \
+                 the admin can grant it with `gh permissions grant`"
+            );
+        }
+        Ok((owner, name))
+    }
+
+    /// Grant or revoke the permission to approve and merge PRs
+    async fn handle_permission_command(
+        ctx: &Context,
+        state: &State,
+        chat_id: ChatId,
+        msg: &Message,
+        command: PermissionSubcommands,
+    ) -> Result<()> {
+        Self::require_admin(ctx, state, msg).await?;
+        Self::require_verified(ctx, msg).await?;
+        let (repo, addr, allowed) = match command {
+            PermissionSubcommands::Grant { repo, addr } => (repo, addr, true),
+            PermissionSubcommands::Revoke { repo, addr } => (repo, addr, false),
+        };
+        let repo = state.db.resolve_repository(&repo).await?;
+        let contact = Contact::lookup_id_by_addr(ctx, &addr, Origin::Unknown)
+            .await?
+            .with_context(|| format!("No contact with the address {addr}"))?;
+        state
+            .db
+            .set_merge_permission(contact, repo, allowed)
+            .await?;
+        let name = state.db.get_name(repo).await?;
+        let text = if allowed {
+            format!("{addr} may now approve and merge PRs of {name}")
+        } else {
+            format!("{addr} may no longer approve and merge PRs of {name}")
+        };
+        send_text_msg(ctx, chat_id, text).await?;
+        Ok(())
+    }
+
+    /// Close, reopen, label or assign an issue or PR with the token of a contact
+    async fn triage(
+        ctx: &Context,
+        state: &State,
+        chat_id: ChatId,
+        token: &str,
+        command: TriageSubcommands,
+        pull_request: bool,
+    ) -> Result<()> {
+        let (repo, number) = command.target();
+        let repo = state.db.resolve_repository(repo).await?;
+        // only act on repositories someone deliberately added to the bot
+        if state.db.get_api_key(repo).await?.is_none() {
+            bail!("The bot has no credentials for this repository");
+        }
+        let owner = state.db.get_owner(repo).await?;
+        let name = state.db.get_name(repo).await?;
+        let item = get_issue(&owner, &name, number, token).await?;
+        if item.is_pull_request() != pull_request {
+            let kind = if item.is_pull_request() {
+                "a PR"
+            } else {
+                "an issue"
+            };
+            bail!("{owner}/{name}#{number} is {kind}");
+        }
+
+        let mut note = String::new();
+        let text = match command {
+            TriageSubcommands::Close { not_planned, .. } => {
+                let reason = match (pull_request, not_planned) {
+                    (true, _) => None,
+                    (false, true) => Some("not_planned"),
+                    (false, false) => Some("completed"),
+                };
+                set_issue_state(&owner, &name, number, token, "closed", reason).await?;
+                "Closed".to_string()
+            }
+            TriageSubcommands::Reopen { .. } => {
+                set_issue_state(&owner, &name, number, token, "open", None).await?;
+                "Reopened".to_string()
+            }
+            TriageSubcommands::Label { labels, .. } => {
+                add_labels(&owner, &name, number, token, &labels).await?;
+                "Labeled".to_string()
+            }
+            TriageSubcommands::Unlabel { labels, .. } => {
+                let is_set = |label: &String| {
+                    item.labels
+                        .iter()
+                        .any(|set| set.name.eq_ignore_ascii_case(label))
+                };
+                let missing = labels.iter().filter(|label| !is_set(label)).join(", ");
+                let mut removed = vec![];
+                // github matches label names exactly, so use the names as they are set
+                for label in &item.labels {
+                    if labels
+                        .iter()
+                        .any(|name| name.eq_ignore_ascii_case(&label.name))
+                        && remove_label(&owner, &name, number, token, &label.name).await?
+                    {
+                        removed.push(label.name.as_str());
+                    }
+                }
+                if removed.is_empty() {
+                    bail!("{owner}/{name}#{number} has none of the labels {missing}");
+                }
+                if !missing.is_empty() {
+                    note = format!("\nThese labels weren't set: {missing}");
+                }
+                format!("Removed {} from", removed.join(", "))
+            }
+            TriageSubcommands::Assign { assignees, .. } => {
+                let item = add_assignees(&owner, &name, number, token, &assignees).await?;
+                let skipped = assignees
+                    .iter()
+                    .filter(|login| {
+                        !item
+                            .assignees
+                            .iter()
+                            .any(|user| user.login.eq_ignore_ascii_case(login))
+                    })
+                    .join(", ");
+                if !skipped.is_empty() {
+                    bail!("Github didn't assign {skipped}, they may lack access to the repository");
+                }
+                "Assigned".to_string()
+            }
+        };
+        send_text_msg(
+            ctx,
+            chat_id,
+            format!("{text} {owner}/{name}#{number}: {}{note}", item.html_url),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Get the github token of the sender of a message or explain how to store one
+    ///
+    /// Only encrypted and signed messages can use the token, others could be sent by anyone.
+    async fn contact_token(state: &State, msg: &Message) -> Result<String> {
+        Self::require_encrypted(msg)?;
+        state.db.get_token(msg.get_from_id()).await?.context(
+            "This needs your github token, store it with `gh token set <token>` \
+             in a 1:1 chat with the bot",
+        )
+    }
+
+    /// Make the attachment of a message available to github and return markdown linking it
+    ///
+    /// Github has no api to upload files to issues, so the bot serves them itself,
+    /// if it has a public url. Returns `None` if there is no attachment or it can't be served.
+    async fn publish_attachment(
+        ctx: &Context,
+        state: &State,
+        msg: &Message,
+    ) -> Result<Option<String>> {
+        let (Some(path), Some(base)) = (msg.get_file(ctx), &state.attachments_url) else {
+            return Ok(None);
+        };
+        let filename = msg
+            .get_filename()
+            .unwrap_or_else(|| "attachment".to_string());
+        // random names, so that the attachments of other issues can't be guessed
+        let name = format!(
+            "{:016x}-{}",
+            rand::random::<u64>(),
+            filename.replace(|c: char| !c.is_ascii_alphanumeric() && c != '.', "_")
+        );
+        tokio::fs::copy(path, Path::new(ATTACHMENTS_DIR).join(&name)).await?;
+        let url = attachment_url(base, &name);
+        Ok(Some(match msg.get_viewtype() {
+            Viewtype::Image | Viewtype::Gif | Viewtype::Sticker => format!("![{filename}]({url})"),
+            _ => format!("[{filename}]({url})"),
+        }))
+    }
+
+    /// Store or forget the github token of a contact
+    async fn handle_token_command(
+        ctx: &Context,
+        state: &State,
+        chat_id: ChatId,
+        contact: ContactId,
+        command: TokenSubcommands,
+    ) -> Result<()> {
+        let text = match command {
+            TokenSubcommands::Set { token } => {
+                let chat = Chat::load_from_db(ctx, chat_id).await?;
+                if chat.typ != Chattype::Single {
+                    bail!("Tokens are only accepted in a 1:1 chat with the bot, revoke this one");
+                }
+                let user = get_authenticated_user(&token)
+                    .await
+                    .context("The token was rejected by github")?;
+                state.db.set_token(contact, &token).await?;
+                // the token proves the login, so it's linked as well
+                state.db.set_login(contact, &user.login).await?;
+                format!("Stored the token of {}", user.login)
+            }
+            TokenSubcommands::Remove => {
+                state.db.remove_token(contact).await?;
+                "Removed your token".to_string()
+            }
+        };
+        send_text_msg(ctx, chat_id, text).await?;
+        Ok(())
+    }
+
+    /// Link a contact to a github login once it published the code it got before
+    async fn link(state: &State, contact: ContactId, login: &str) -> Result<String> {
+        if !is_valid_login(login) {
+            bail!("`{login}` isn't a github login, they only have letters, digits and dashes");
+        }
+        if let Some(token) = state.db.get_token(contact).await? {
+            let user = get_authenticated_user(&token).await?;
+            if user.login.eq_ignore_ascii_case(login) {
+                state.db.set_login(contact, &user.login).await?;
+                return Ok(format!("Linked you to {}", user.login));
+            }
+        }
+        if let Some((pending, code)) = state.db.get_link_code(contact).await? {
+            if pending.eq_ignore_ascii_case(login) {
+                let published = get_gists(login).await?.iter().any(|gist| {
+                    gist.description
+                        .as_deref()
+                        .map_or(false, |description| description.contains(&code))
+                });
+                if !published {
+                    return Ok(format!(
+                        "None of the public gists of {login} has `{code}` in its description yet"
+                    ));
+                }
+                state.db.set_login(contact, login).await?;
+                return Ok(format!(
+                    "Linked you to {login}, the gist can be deleted now"
+                ));
+            }
+        }
+        let code = link_code();
+        state.db.set_link_code(contact, login, &code).await?;
+        Ok(format!(
+            "To prove that you are {login}, create a public gist with the description \
+             `{code}` at https://gist.github.com and send `gh link {login}` again"
+        ))
+    }
+
+    /// Change the templates of the messages a chat receives
+    async fn handle_format_command(
+        ctx: &Context,
+        state: &State,
+        chat_id: ChatId,
+        command: FormatSubcommands,
+    ) -> Result<()> {
+        let text = match command {
+            FormatSubcommands::Set { event, template } => {
+                let template = template.join(" ");
+                let preview =
+                    message::render_detached(&event.example(), Some(template.clone())).await?;
+                state.db.set_template(chat_id, event, &template).await?;
+                format!("Messages about {event} events now look like this:\n\n{preview}")
+            }
+            FormatSubcommands::Reset { event } => {
+                state.db.reset_template(chat_id, event).await?;
+                match event {
+                    Some(event) => format!("Messages about {event} events use the default again"),
+                    None => "All messages use the default templates again".to_string(),
+                }
+            }
+            FormatSubcommands::Preview { event } => {
+                let template = state.db.get_template(chat_id, event).await?;
+                message::render_detached(&event.example(), template).await?
+            }
+        };
+        send_text_msg(ctx, chat_id, text).await?;
+        Ok(())
+    }
+
+    /// Change and list supported repositories
+    async fn handle_repo_command(
+        ctx: &Context,
+        state: &State,
+        chat_id: ChatId,
+        command: RepoSubcommands,
+    ) -> Result<()> {
+        match command {
+            RepoSubcommands::List => {
+                let repos = state.db.get_repositories().await?;
+                let text = if !repos.is_empty() {
+                    format!(
+                        "Available repositories:\n{}",
+                        repos
+                            .iter()
+                            .map(|repo| format!(
+                                "{} ({}): https://github.com/{}",
+                                repo.full_name(),
+                                repo.id,
+                                repo.full_name()
+                            ))
+                            .join("\n")
+                    )
+                } else {
+                    "No repositories have been added yet".to_string()
+                };
+                send_text_msg(ctx, chat_id, text).await?;
+            }
+            RepoSubcommands::Add {
+                owner,
                 repository,
-                pull_request: pr,
-            }) => {
-                let subs = state
+                api_key,
+                polling,
+            } => {
+                let SharedRepo { id, url, .. } =
+                    get_repository(&owner, &repository, &api_key).await?;
+                let events = state.db.get_subscribed_events(id).await?;
+                let hook_id = if polling {
+                    0
+                } else {
+                    create_or_adopt_hook(
+                        &owner,
+                        &repository,
+                        &api_key,
+                        &state.ip,
+                        &events,
+                        state.secret.as_deref(),
+                    )
+                    .await?
+                };
+                state
                     .db
-                    .get_subscribers(repository.id, Family::Pr { pr_action: action })
-                    .await
-                    .unwrap();
-                send_text_to_all(
-                    &subs,
-                    &format!(
-                        "User {} trigged event `{action}` on PR {}",
-                        sender.login, pr.title
-                    ),
+                    .add_repository(Repository {
+                        name: &repository,
+                        owner: &owner,
+                        hook_id,
+                        id,
+                        url: &url,
+                        api_key: &api_key,
+                        polling,
+                    })
+                    .await?;
+                state.db.set_events(id, &events).await?;
+                let text = if polling {
+                    info!("Polling events of repository {repository}");
+                    "Successfully added repository, its events are polled"
+                } else {
+                    info!("Added new webhook for repository {repository}");
+                    "Successfully added webhook"
+                };
+                send_text_msg(ctx, chat_id, text.to_string()).await?;
+            }
+            RepoSubcommands::Remove {
+                repository,
+                api_key,
+            } => {
+                let id = state.db.resolve_repository(&repository).await?;
+                let hook_id = state.db.get_hook_id(id).await?;
+                let owner = state.db.get_owner(id).await?;
+                let repo = state.db.get_name(id).await?;
+                if !state.db.is_polling(id).await? {
+                    remove_hook(&owner, &repo, hook_id, &api_key).await?;
+                }
+                state.db.remove_repository(id).await?;
+                info!("removed repository {owner}/{repo}");
+                send_text_msg(ctx, chat_id, "Successfully removed repository".to_string()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the webhooks of repositories
+    async fn handle_health_command(
+        ctx: &Context,
+        state: &State,
+        chat_id: ChatId,
+        msg: &Message,
+        command: HealthSubcommands,
+    ) -> Result<()> {
+        match command {
+            HealthSubcommands::Admin => {
+                Self::require_admin(ctx, state, msg).await?;
+                let admin = state.admin.as_deref().unwrap_or_default();
+                state.db.set_admin_chat(chat_id, admin).await?;
+                send_text_msg(
                     ctx,
+                    chat_id,
+                    "This chat now receives alerts about failing webhooks".to_string(),
                 )
                 .await?;
             }
+            HealthSubcommands::Check { repository } => {
+                let repo = state.db.resolve_repository(&repository).await?;
+                let (name, health) = Self::hook_health(state, repo).await?;
+                send_text_msg(ctx, chat_id, health.report(&name)).await?;
+            }
+            HealthSubcommands::Redeliver { repository } => {
+                let repo = state.db.resolve_repository(&repository).await?;
+                let count = Self::redeliver_failed(state, repo).await?;
+                send_text_msg(
+                    ctx,
+                    chat_id,
+                    format!("Requested redelivery of {count} failed deliveries"),
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a parsed webhook-event
+    async fn handle_webhook(
+        state: Arc<State>,
+        ctx: &Context,
+        event: WebhookEvent,
+    ) -> anyhow::Result<()> {
+        info!("Handling webhook event {}", event);
+        let subs = match &event {
+            WebhookEvent::Issue(event) => {
+                Self::matching_subscribers(
+                    &state,
+                    event.repository.id,
+                    "issue",
+                    &event.action.to_string(),
+                    &Subject::from(event),
+                )
+                .await?
+            }
+            WebhookEvent::PR(event) => {
+                let repo = event.repository.id;
+                let subscriptions = state
+                    .db
+                    .get_subscriptions_to(repo, "pr", &event.action.to_string())
+                    .await?;
+                // the changed files are only fetched if a subscription filters by them
+                let files = if subscriptions
+                    .iter()
+                    .any(|(_, filter)| !filter.paths.is_empty())
+                {
+                    let files = Self::pr_files(&state, repo, &event.pull_request).await;
+                    Some(files.unwrap_or_else(|err| {
+                        error!("failed to get the changed files of a PR: {err}");
+                        vec![]
+                    }))
+                } else {
+                    None
+                };
+                let subject = Subject {
+                    paths: files
+                        .as_ref()
+                        .map(|files| files.iter().map(String::as_str).collect()),
+                    ..Subject::from(event)
+                };
+                matching_chats(subscriptions, &subject)
+            }
+            WebhookEvent::Push(event) => {
+                Self::matching_subscribers(
+                    &state,
+                    event.repository.id,
+                    "push",
+                    "all",
+                    &Subject::from(event),
+                )
+                .await?
+            }
+            // comments are only delivered as personal notifications
+            WebhookEvent::Comment(_) => vec![],
+        };
+        Self::notify(ctx, &state, &subs, &event).await?;
+        Self::notify_personally(ctx, &state, &subs, &event).await
+    }
+
+    /// Send a notification as reply to the first one the chat got about the same issue or PR
+    async fn send_notification(
+        ctx: &Context,
+        state: &State,
+        chat: ChatId,
+        text: String,
+        event: &WebhookEvent,
+    ) -> Result<()> {
+        let repo = event.repository().id;
+        let Some(number) = event.number() else {
+            send_text_msg(ctx, chat, text).await?;
+            return Ok(());
         };
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some(text));
+        let mut threaded = false;
+        if let Some(start) = state.db.get_thread_start(chat, repo, number).await? {
+            // the first message may have been deleted or moved to another chat,
+            // then the next one starts the thread
+            match Message::load_from_db(ctx, start).await {
+                Ok(quote) if quote.get_chat_id() == chat => {
+                    msg.set_quote(ctx, Some(&quote)).await?;
+                    threaded = true;
+                }
+                Ok(_) => debug!("can't quote {start}, it isn't in {chat} anymore"),
+                Err(err) => debug!("can't quote {start}: {err}"),
+            }
+        }
+        let msg = send_msg(ctx, chat, &mut msg).await?;
+        state.db.add_notification(msg, repo, number).await?;
+        if !threaded {
+            state.db.set_thread_start(chat, repo, number, msg).await?;
+        }
         Ok(())
     }
 
+    /// Tell linked contacts about events that concern them in their 1:1 chat
+    ///
+    /// Chats that already got the event through a subscription are skipped.
+    async fn notify_personally(
+        ctx: &Context,
+        state: &State,
+        notified: &[ChatId],
+        event: &WebhookEvent,
+    ) -> Result<()> {
+        for (login, reason) in personal::recipients(event) {
+            for contact in state.db.get_personal_contacts(&login).await? {
+                let chat = ChatId::create_for_contact(ctx, contact).await?;
+                if notified.contains(&chat) {
+                    continue;
+                }
+                let template = state.db.get_template(chat, event.kind()).await?;
+                let text = match message::render_detached(event, template).await {
+                    Ok(text) => text,
+                    Err(_) => message::render_detached(event, None).await?,
+                };
+                Self::send_notification(ctx, state, chat, format!("{reason}\n{text}"), event)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Send the message about an event to chats, rendered with their own template if they set one
+    async fn notify(
+        ctx: &Context,
+        state: &State,
+        chats: &[ChatId],
+        event: &WebhookEvent,
+    ) -> Result<()> {
+        for chat in chats {
+            let template = state.db.get_template(*chat, event.kind()).await?;
+            let text = match message::render_detached(event, template).await {
+                Ok(text) => text,
+                Err(err) => {
+                    warn!("template of {chat} failed, falling back to the default: {err}");
+                    message::render_detached(event, None).await?
+                }
+            };
+            Self::send_notification(ctx, state, *chat, text, event).await?;
+        }
+        Ok(())
+    }
+
+    /// Return the chats subscribed to an action whose filter lets the event through
+    async fn matching_subscribers(
+        state: &State,
+        repo: usize,
+        family: &str,
+        action: &str,
+        subject: &Subject<'_>,
+    ) -> Result<Vec<ChatId>> {
+        let subscriptions = state.db.get_subscriptions_to(repo, family, action).await?;
+        Ok(matching_chats(subscriptions, subject))
+    }
+
+    /// Return the files changed by a PR, which are only fetched once per head commit
+    async fn pr_files(state: &State, repo: usize, pr: &PR) -> Result<Vec<String>> {
+        let key = (repo, pr.number, pr.head.sha.clone());
+        let cached = state.pr_files.lock().unwrap().get(&key).cloned();
+        if let Some(files) = cached {
+            return Ok(files);
+        }
+        let api_key = state
+            .db
+            .get_api_key(repo)
+            .await?
+            .context("no api key stored for this repository")?;
+        let owner = state.db.get_owner(repo).await?;
+        let name = state.db.get_name(repo).await?;
+        let files = get_pr_files(&owner, &name, pr.number, &api_key).await?;
+
+        let mut cache = state.pr_files.lock().unwrap();
+        if cache.len() >= PR_FILES_CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert(key, files.clone());
+        Ok(files)
+    }
+
     pub async fn stop(self) {
         self.dc_ctx.stop_io().await;
         self.hook_server.stop()
     }
 }
+
+/// Chats of the subscriptions whose filter lets the event through
+fn matching_chats(subscriptions: Vec<(ChatId, Filter)>, subject: &Subject) -> Vec<ChatId> {
+    subscriptions
+        .into_iter()
+        .filter(|(_, filter)| filter.matches(subject))
+        .map(|(chat, _)| chat)
+        .unique()
+        .collect()
+}
+
+/// Describe a subscriber list the way it is written in commands, e.g. `pr opened`
+fn describe_list(list: &str) -> String {
+    list.replacen('_', " ", 1)
+}
+
+/// Describe a subscription with its filter, e.g. `pr opened --label bug`
+fn describe_subscription(list: &str, filter: &Filter) -> String {
+    if filter.is_empty() {
+        describe_list(list)
+    } else {
+        format!("{} {filter}", describe_list(list))
+    }
+}
+
+/// List which subscriptions changed and which didn't, leaving out empty categories
+fn change_report(categories: &[(&str, &[String])]) -> String {
+    categories
+        .iter()
+        .filter(|(_, lists)| !lists.is_empty())
+        .map(|(label, lists)| format!("{label}: {}", lists.join(", ")))
+        .join("\n")
+}
+
+/// Note for the heading of a list that was cut at the page limit
+///
+/// The list may have been filtered, so the note counts the items that were searched.
+fn truncation_note(truncated: bool, searched: usize, kind: &str) -> String {
+    if truncated {
+        format!(" (searched the first {searched} open {kind})")
+    } else {
+        String::new()
+    }
+}
+
+/// One-time code a contact publishes to prove it owns a github login
+fn link_code() -> String {
+    format!("deltachat-bot-{:016x}", rand::thread_rng().gen::<u64>())
+}
@@ -1,15 +1,26 @@
 //! Integration fo SurrealDB
 
 use crate::{
-    bot::GitRepository,
-    parser::{Commands, Family},
+    bot::GitRepository, filter::Filter, message::EventKind, personal::PERSONAL_EVENTS, vault::Vault,
 };
 use anyhow::{bail, Result};
-use deltachat::chat::ChatId;
+use deltachat::{chat::ChatId, contact::ContactId, message::MsgId};
+use itertools::Itertools;
+use std::collections::BTreeMap;
 use surrealdb::{
     sql::{Number, Object, Value},
     Datastore, Session,
 };
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum RepoError {
+    #[error("Unknown repository `{0}`, see `gh repositories list`")]
+    NotFound(String),
+
+    #[error("Repository `{0}` is ambiguous, use one of: {1}")]
+    Ambiguous(String, String),
+}
 
 #[derive(Default)]
 pub struct Repository<'a> {
@@ -18,89 +29,346 @@ pub struct Repository<'a> {
     pub hook_id: usize,
     pub id: usize,
     pub url: &'a str,
+    pub api_key: &'a str,
+    pub polling: bool,
 }
 
+/// Position in the event timeline of a polled repository
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PollCursor {
+    /// Etag of the last response, to let github answer `304 Not Modified`
+    pub etag: Option<String>,
+    /// Id of the newest event that has been processed
+    pub since: Option<u64>,
+    /// Time of the newest event that has been processed, to find what the timeline lost
+    pub updated: Option<String>,
+}
+
+/// Prefixes of subscriber lists and the webhook event they are filled by
+const LIST_EVENTS: [(&str, &str); 3] = [
+    ("pr_", "pull_request"),
+    ("issue_", "issues"),
+    ("push_", "push"),
+];
+
 pub struct DB {
     db: Datastore,
     session: Session,
+    /// Encrypts tokens and api keys
+    vault: Vault,
 }
 
 #[allow(unused)]
 impl DB {
-    pub async fn new(store: &str) -> Self {
+    pub async fn new(store: &str, db_key: &str) -> Self {
         let db = Datastore::new(store).await.unwrap();
         Self {
             db,
             session: Session::for_kv().with_ns("bot").with_db("bot"),
+            vault: Vault::new(db_key),
+        }
+    }
+
+    /// Encrypt the tokens and api keys that were stored before secrets were encrypted
+    pub async fn seal_plaintext_secrets(&self) -> Result<()> {
+        for (table, field) in [("repo", "api_key"), ("contact", "token")] {
+            let mut resp = self
+                .execute(&format!("SELECT id, {field} FROM {table}"))
+                .await?;
+            let resp = resp.remove(0).result?;
+
+            let Value::Array(rows) = resp else {
+                continue;
+            };
+            for row in rows.iter() {
+                let Value::Object(Object(inner)) = row else {
+                    continue;
+                };
+                if let (Some(Value::Thing(id)), Some(secret @ Value::Strand(_))) =
+                    (inner.get("id"), inner.get(field))
+                {
+                    let secret = secret.clone().as_string();
+                    if Vault::is_sealed(&secret) {
+                        continue;
+                    }
+                    let vars = BTreeMap::from([(
+                        "secret".to_string(),
+                        Value::from(self.vault.seal(&secret)?),
+                    )]);
+                    self.execute_with(&format!("UPDATE {id} SET {field} = $secret"), vars)
+                        .await?;
+                }
+            }
         }
+        Ok(())
     }
 
     async fn execute(&self, ast: &str) -> Result<Vec<surrealdb::Response>, surrealdb::Error> {
         self.db.execute(ast, &self.session, None, false).await
     }
 
-    /// Return which db_table has to be queried for the specific action
-    fn get_list_prefix(family: Family) -> String {
-        match family {
-            crate::parser::Family::Pr { pr_action } => format!("pr_{}", pr_action),
-            crate::parser::Family::Issue { issue_action } => {
-                format!("issue_{}", issue_action)
+    /// Execute a statement with variables, for values that can't be inlined safely
+    async fn execute_with(
+        &self,
+        ast: &str,
+        vars: BTreeMap<String, Value>,
+    ) -> Result<Vec<surrealdb::Response>, surrealdb::Error> {
+        self.db.execute(ast, &self.session, Some(vars), false).await
+    }
+
+    /// Return all subscriber lists of a repository together with their subscribers
+    async fn get_lists(&self, repo: usize) -> Result<Vec<(String, Vec<ChatId>)>> {
+        let mut resp = self
+            .execute(&format!("SELECT * FROM {repo}:{repo}"))
+            .await?;
+        let resp = resp.remove(0).result?;
+
+        let mut lists = vec![];
+        if let Value::Object(Object(fields)) = unwrap_array(&resp) {
+            for (list, chats) in fields {
+                if let Value::Array(chats) = unwrap_array(chats) {
+                    let chats = chats
+                        .iter()
+                        .filter_map(|chat| match chat {
+                            Value::Number(Number::Int(chat_id)) => {
+                                Some(ChatId::new(*chat_id as u32))
+                            }
+                            _ => None,
+                        })
+                        .collect();
+                    lists.push((list.clone(), chats));
+                }
             }
         }
+        Ok(lists)
     }
 
-    async fn change_subscriber(&self, command: Commands, chat: ChatId, add: bool) {
-        if let Commands::Subscribe { repo, family } | Commands::Unsubscribe { repo, family } =
-            command
-        {
-            let list = Self::get_list_prefix(family);
-            let action = if add { "+=" } else { "-=" };
-            self.execute(&format!(
-                "UPDATE {repo}:{repo} SET {list} {action} [{}]",
-                chat.to_u32()
-            ))
-            .await
-            .unwrap();
+    /// Add or remove a ChatId and return whether the list changed
+    async fn change_subscriber(
+        &self,
+        repo: usize,
+        list: &str,
+        chat: ChatId,
+        add: bool,
+    ) -> Result<bool> {
+        let subscribed = self
+            .get_lists(repo)
+            .await?
+            .iter()
+            .any(|(name, chats)| name == list && chats.contains(&chat));
+        if subscribed == add {
+            return Ok(false);
+        }
+        let action = if add { "+=" } else { "-=" };
+        self.execute(&format!(
+            "UPDATE {repo}:{repo} SET {list} {action} [{}]",
+            chat.to_u32()
+        ))
+        .await?;
+        if !add {
+            self.set_filter(repo, list, chat, &Filter::default())
+                .await?;
         }
+        Ok(true)
     }
 
-    /// Add a ChatId as subscriber to an action
-    pub async fn add_subscriber(&self, command: Commands, chat: ChatId) {
-        self.change_subscriber(command, chat, true).await;
+    /// Add a ChatId to a subscriber list, returns false if it already was subscribed
+    pub async fn add_subscriber(&self, repo: usize, list: &str, chat: ChatId) -> Result<bool> {
+        self.change_subscriber(repo, list, chat, true).await
     }
 
-    /// Remove a ChatId from subscribers to an action
-    pub async fn remove_subscriber(&self, command: Commands, chat: ChatId) {
-        self.change_subscriber(command, chat, false).await;
+    /// Remove a ChatId from a subscriber list, returns false if it wasn't subscribed
+    pub async fn remove_subscriber(&self, repo: usize, list: &str, chat: ChatId) -> Result<bool> {
+        self.change_subscriber(repo, list, chat, false).await
     }
 
-    /// Return all ChatIds which subscribed to to an action
-    pub async fn get_subscribers(&self, repo: usize, family: Family) -> Result<Vec<ChatId>> {
-        let list = Self::get_list_prefix(family);
-        let mut query = self
-            .execute(&format!("SELECT {list} FROM {repo}:{repo}"))
+    /// Return all ChatIds which subscribed to an action, directly or by a wildcard
+    pub async fn get_subscribers(
+        &self,
+        repo: usize,
+        family: &str,
+        action: &str,
+    ) -> Result<Vec<ChatId>> {
+        Ok(self
+            .get_subscriptions_to(repo, family, action)
+            .await?
+            .into_iter()
+            .map(|(chat, _)| chat)
+            .unique()
+            .collect())
+    }
+
+    /// Return the subscriptions to an action together with their filters
+    ///
+    /// A chat appears once for every list it subscribed to the action with.
+    pub async fn get_subscriptions_to(
+        &self,
+        repo: usize,
+        family: &str,
+        action: &str,
+    ) -> Result<Vec<(ChatId, Filter)>> {
+        let lists = [
+            format!("{family}_{action}"),
+            format!("{family}_all"),
+            "all".to_string(),
+        ];
+        let filters = self.get_filters(repo).await?;
+        Ok(self
+            .get_lists(repo)
+            .await?
+            .into_iter()
+            .filter(|(list, _)| lists.contains(list))
+            .flat_map(|(list, chats)| chats.into_iter().map(move |chat| (list.clone(), chat)))
+            .map(|(list, chat)| {
+                let filter = filters
+                    .iter()
+                    .find(|(other_chat, other_list, _)| *other_chat == chat && *other_list == list)
+                    .map(|(_, _, filter)| filter.clone())
+                    .unwrap_or_default();
+                (chat, filter)
+            })
+            .collect())
+    }
+
+    /// Set the filter of a subscription, returns false if it didn't change
+    ///
+    /// An empty filter removes the stored filter.
+    pub async fn set_filter(
+        &self,
+        repo: usize,
+        list: &str,
+        chat: ChatId,
+        filter: &Filter,
+    ) -> Result<bool> {
+        if self.get_filter(repo, list, chat).await? == *filter {
+            return Ok(false);
+        }
+        let chat = chat.to_u32();
+        let vars = BTreeMap::from([
+            ("list".to_string(), Value::from(list.to_string())),
+            (
+                "filter".to_string(),
+                Value::from(serde_json::to_string(filter)?),
+            ),
+        ]);
+        self.execute_with(
+            &format!("DELETE filters WHERE repo = {repo} AND chat = {chat} AND list = $list"),
+            vars.clone(),
+        )
+        .await?;
+        if !filter.is_empty() {
+            self.execute_with(
+                &format!(
+                    "CREATE filters SET repo = {repo}, chat = {chat}, \
+                     list = $list, filter = $filter"
+                ),
+                vars,
+            )
             .await?;
+        }
+        Ok(true)
+    }
 
-        let rsp1 = query.remove(0);
-        if let Ok(val) = rsp1.result {
-            let obj = unwrap_array(&val);
-            if let Value::Object(obj) = obj {
-                let numbers = unwrap_array(obj.values().next().unwrap());
-                if let Value::Array(arr) = numbers {
-                    let ids = arr
-                        .iter()
-                        .map(|num| {
-                            if let Value::Number(Number::Int(chat_id)) = num {
-                                return ChatId::new(*chat_id as u32);
-                            }
-                            panic!("can't convert number");
-                        })
-                        .collect::<Vec<_>>();
-                    return Ok(ids);
+    /// Get the filter of a subscription, which is empty if none was set
+    pub async fn get_filter(&self, repo: usize, list: &str, chat: ChatId) -> Result<Filter> {
+        Ok(self
+            .get_filters(repo)
+            .await?
+            .into_iter()
+            .find(|(other_chat, other_list, _)| *other_chat == chat && other_list == list)
+            .map(|(_, _, filter)| filter)
+            .unwrap_or_default())
+    }
+
+    /// Return all filters of a repository with the chat and list they belong to
+    async fn get_filters(&self, repo: usize) -> Result<Vec<(ChatId, String, Filter)>> {
+        let mut resp = self
+            .execute(&format!(
+                "SELECT chat, list, filter FROM filters WHERE repo = {repo}"
+            ))
+            .await?;
+        let resp = resp.remove(0).result?;
+
+        let mut filters = vec![];
+        if let Value::Array(rows) = resp {
+            for row in rows.iter() {
+                let Value::Object(Object(fields)) = row else {
+                    continue;
                 };
-            };
-        };
-        Ok(vec![])
+                if let (
+                    Some(Value::Number(Number::Int(chat))),
+                    Some(list @ Value::Strand(_)),
+                    Some(filter @ Value::Strand(_)),
+                ) = (fields.get("chat"), fields.get("list"), fields.get("filter"))
+                {
+                    filters.push((
+                        ChatId::new(*chat as u32),
+                        list.clone().as_string(),
+                        serde_json::from_str(&filter.clone().as_string())?,
+                    ));
+                }
+            }
+        }
+        Ok(filters)
+    }
+
+    /// Return all repositories and lists a chat is subscribed to
+    pub async fn get_subscriptions(&self, chat: ChatId) -> Result<Vec<(usize, String)>> {
+        let mut subscriptions = vec![];
+        for repo in self.get_repository_ids().await? {
+            for (list, chats) in self.get_lists(repo).await? {
+                if chats.contains(&chat) {
+                    subscriptions.push((repo, list));
+                }
+            }
+        }
+        Ok(subscriptions)
+    }
+
+    /// Remove a chat from all lists of a repository and return the lists it was removed from
+    pub async fn remove_all_subscriptions(&self, repo: usize, chat: ChatId) -> Result<Vec<String>> {
+        let mut removed = vec![];
+        for (list, chats) in self.get_lists(repo).await? {
+            if chats.contains(&chat) {
+                self.execute(&format!(
+                    "UPDATE {repo}:{repo} SET {list} -= [{}]",
+                    chat.to_u32()
+                ))
+                .await?;
+                removed.push(list);
+            }
+        }
+        self.execute(&format!(
+            "DELETE filters WHERE repo = {repo} AND chat = {}",
+            chat.to_u32()
+        ))
+        .await?;
+        Ok(removed)
+    }
+
+    /// Return the webhook events at least one chat of a repository is subscribed to
+    pub async fn get_subscribed_events(&self, repo: usize) -> Result<Vec<&'static str>> {
+        let mut events = vec![];
+        for (list, chats) in self.get_lists(repo).await? {
+            if chats.is_empty() {
+                continue;
+            }
+            if list == "all" {
+                events.extend(LIST_EVENTS.iter().map(|(_, event)| *event));
+            } else if let Some((_, event)) = LIST_EVENTS
+                .iter()
+                .find(|(prefix, _)| list.starts_with(prefix))
+            {
+                events.push(*event);
+            }
+        }
+        // personal notifications can come from any repository
+        if self.has_personal_contacts().await? {
+            events.extend(PERSONAL_EVENTS);
+        }
+        events.sort_unstable();
+        events.dedup();
+        Ok(events)
     }
 
     /// Add a repository to the collection of repositories
@@ -111,22 +379,47 @@ impl DB {
             id,
             url,
             owner,
+            api_key,
+            polling,
         } = repo;
-        let stm = format!("CREATE repo:{id} SET repo_id = {id}, url = '{url}', hook_id = {hook_id}, owner='{owner}', name = '{name}'");
-        self.execute(&stm).await?;
+        let api_key = self.vault.seal(api_key)?;
+        let vars = [
+            ("url", url),
+            ("owner", owner),
+            ("name", name),
+            ("api_key", &api_key),
+        ]
+        .map(|(var, value)| (var.to_string(), Value::from(value.to_string())));
+        self.execute_with(
+            &format!(
+                "CREATE repo:{id} SET repo_id = {id}, url = $url, hook_id = {hook_id}, \
+                 owner = $owner, name = $name, api_key = $api_key, events = [], \
+                 polling = {polling}"
+            ),
+            BTreeMap::from(vars),
+        )
+        .await?;
         Ok(())
     }
 
     /// Remove repository from the collection of repositories
     pub async fn remove_repository(&self, id: usize) -> Result<()> {
-        self.execute(&format!("DELETE repos:{id}")).await?;
+        self.execute(&format!(
+            "DELETE repo:{id}; \
+             DELETE {id}:{id}; \
+             DELETE filters WHERE repo = {id}; \
+             DELETE threads WHERE repo = {id}; \
+             DELETE notification WHERE repo = {id}; \
+             UPDATE contact SET merge_repos -= [{id}] WHERE merge_repos CONTAINS {id}"
+        ))
+        .await?;
         Ok(())
     }
 
     /// Get all repositories
     pub async fn get_repositories(&self) -> Result<Vec<GitRepository>> {
-        let stm = format!("SELECT repo_id, name FROM repo;");
-        let mut resp = self.execute(&stm).await?;
+        let stm = include_str!("queries/repos.sql");
+        let mut resp = self.execute(stm).await?;
         let mut resp = resp.remove(0).result?;
 
         if let Value::Array(arr) = resp {
@@ -137,7 +430,9 @@ impl DB {
                         let Object(inner) = obj;
                         Some(GitRepository {
                             name: inner.get("name")?.clone().as_string(),
-                            id: inner.get("repo_id")?.clone().as_int(),
+                            owner: inner.get("owner")?.clone().as_string(),
+                            url: inner.get("url")?.clone().as_string(),
+                            id: inner.get("repo_id")?.clone().as_int() as usize,
                         })
                     } else {
                         None
@@ -149,6 +444,33 @@ impl DB {
         }
     }
 
+    /// Find a repository by `owner/name`, by its name if that is unique, or by its id
+    pub async fn resolve_repository(&self, repo: &str) -> Result<usize> {
+        let repos = self.get_repositories().await?;
+        if let Ok(id) = repo.parse::<usize>() {
+            if repos.iter().any(|known| known.id == id) {
+                return Ok(id);
+            }
+        }
+        let found = repos
+            .iter()
+            .filter(|known| match repo.split_once('/') {
+                Some((owner, name)) => {
+                    known.owner.eq_ignore_ascii_case(owner) && known.name.eq_ignore_ascii_case(name)
+                }
+                None => known.name.eq_ignore_ascii_case(repo),
+            })
+            .collect::<Vec<_>>();
+        match found.as_slice() {
+            [] => Err(RepoError::NotFound(repo.to_string()))?,
+            [known] => Ok(known.id),
+            _ => Err(RepoError::Ambiguous(
+                repo.to_string(),
+                found.iter().map(|known| known.full_name()).join(", "),
+            ))?,
+        }
+    }
+
     /// Get the ids of all available repositories
     pub async fn get_repository_ids(&self) -> Result<Vec<usize>> {
         let stm = include_str!("queries/repo_ids.sql");
@@ -204,6 +526,545 @@ impl DB {
         bail!("something went wrong")
     }
 
+    /// Get the REST-Api key of one repository
+    ///
+    /// Repositories added before keys were stored don't have one.
+    pub async fn get_api_key(&self, id: usize) -> Result<Option<String>> {
+        let mut resp = self
+            .execute(&format!("SELECT api_key FROM repo:{id}"))
+            .await?;
+        let mut resp = resp.remove(0).result?;
+
+        if let Value::Array(mut arr) = resp {
+            if let Value::Object(obj) = arr.remove(0) {
+                let Object(inner) = obj;
+                return match inner.into_values().next() {
+                    Some(key @ Value::Strand(_)) => Ok(Some(self.vault.open(&key.as_string())?)),
+                    _ => Ok(None),
+                };
+            }
+        };
+        bail!("something went wrong")
+    }
+
+    /// Get the events the webhook of one repository was last registered for
+    pub async fn get_events(&self, id: usize) -> Result<Vec<String>> {
+        let mut resp = self
+            .execute(&format!("SELECT events FROM repo:{id}"))
+            .await?;
+        let mut resp = resp.remove(0).result?;
+
+        if let Value::Array(mut arr) = resp {
+            if let Value::Object(obj) = arr.remove(0) {
+                let Object(inner) = obj;
+                if let Some(Value::Array(events)) = inner.into_values().next() {
+                    return Ok(events.into_iter().map(|e| e.as_string()).collect());
+                }
+                return Ok(vec![]);
+            }
+        };
+        bail!("something went wrong")
+    }
+
+    /// Set the events the webhook of one repository is registered for
+    pub async fn set_events(&self, id: usize, events: &[&str]) -> Result<()> {
+        let events = events.iter().map(|e| format!("'{e}'")).join(", ");
+        self.execute(&format!("UPDATE repo:{id} SET events = [{events}]"))
+            .await?;
+        Ok(())
+    }
+
+    /// Check whether the events of one repository are polled instead of received by webhook
+    pub async fn is_polling(&self, id: usize) -> Result<bool> {
+        let mut resp = self
+            .execute(&format!("SELECT polling FROM repo:{id}"))
+            .await?;
+        let mut resp = resp.remove(0).result?;
+
+        if let Value::Array(mut arr) = resp {
+            if let Value::Object(obj) = arr.remove(0) {
+                let Object(inner) = obj;
+                return Ok(inner.into_values().next().map_or(false, |v| v.is_true()));
+            }
+        };
+        bail!("something went wrong")
+    }
+
+    /// Get the position in the event timeline of a polled repository
+    pub async fn get_poll_cursor(&self, id: usize) -> Result<PollCursor> {
+        let mut resp = self
+            .execute(&format!(
+                "SELECT poll_etag, poll_since, poll_updated FROM repo:{id}"
+            ))
+            .await?;
+        let resp = resp.remove(0).result?;
+
+        let mut cursor = PollCursor::default();
+        if let Value::Object(Object(inner)) = unwrap_array(&resp) {
+            if let Some(etag @ Value::Strand(_)) = inner.get("poll_etag") {
+                cursor.etag = Some(etag.clone().as_string());
+            }
+            if let Some(Value::Number(Number::Int(since))) = inner.get("poll_since") {
+                cursor.since = Some(*since as u64);
+            }
+            if let Some(updated @ Value::Strand(_)) = inner.get("poll_updated") {
+                cursor.updated = Some(updated.clone().as_string());
+            }
+        }
+        Ok(cursor)
+    }
+
+    /// Set the position in the event timeline of a polled repository
+    pub async fn set_poll_cursor(&self, id: usize, cursor: &PollCursor) -> Result<()> {
+        let vars = [("etag", &cursor.etag), ("updated", &cursor.updated)].map(|(var, value)| {
+            let value = value
+                .as_ref()
+                .map_or(Value::None, |value| Value::from(value.as_str()));
+            (var.to_string(), value)
+        });
+        let since = cursor
+            .since
+            .map_or("NONE".to_string(), |since| since.to_string());
+        self.execute_with(
+            &format!(
+                "UPDATE repo:{id} SET poll_etag = $etag, poll_since = {since}, \
+                 poll_updated = $updated"
+            ),
+            BTreeMap::from(vars),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Get the api-url of one repository
+    pub async fn get_url(&self, id: usize) -> Result<String> {
+        let mut resp = self.execute(&format!("SELECT url FROM repo:{id}")).await?;
+        let mut resp = resp.remove(0).result?;
+
+        if let Value::Array(mut arr) = resp {
+            if let Value::Object(obj) = arr.remove(0) {
+                let Object(inner) = obj;
+                return Ok(inner.into_values().next().unwrap().as_string());
+            }
+        };
+        bail!("something went wrong")
+    }
+
+    /// Remember whether the webhook of one repository is failing
+    pub async fn set_failing(&self, id: usize, failing: bool) -> Result<()> {
+        self.execute(&format!("UPDATE repo:{id} SET failing = {failing}"))
+            .await?;
+        Ok(())
+    }
+
+    /// Check whether the webhook of one repository was failing at the last health check
+    pub async fn is_failing(&self, id: usize) -> Result<bool> {
+        let mut resp = self
+            .execute(&format!("SELECT failing FROM repo:{id}"))
+            .await?;
+        let mut resp = resp.remove(0).result?;
+
+        if let Value::Array(mut arr) = resp {
+            if let Value::Object(obj) = arr.remove(0) {
+                let Object(inner) = obj;
+                return Ok(inner.into_values().next().map_or(false, |v| v.is_true()));
+            }
+        };
+        bail!("something went wrong")
+    }
+
+    /// Set the chat which receives health alerts, as chosen by the admin with this address
+    pub async fn set_admin_chat(&self, chat: ChatId, admin: &str) -> Result<()> {
+        let vars = BTreeMap::from([("admin".to_string(), Value::from(admin.to_lowercase()))]);
+        self.execute_with(
+            &format!(
+                "UPDATE config:bot SET admin_chat = {}, admin = $admin",
+                chat.to_u32()
+            ),
+            vars,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Get the chat which receives health alerts, if the admin with this address chose it
+    ///
+    /// A chat chosen by someone else, e.g. a former admin, doesn't count.
+    pub async fn get_admin_chat(&self, admin: &str) -> Result<Option<ChatId>> {
+        let mut resp = self
+            .execute("SELECT admin_chat, admin FROM config:bot")
+            .await?;
+        let resp = resp.remove(0).result?;
+
+        if let Value::Object(Object(inner)) = unwrap_array(&resp) {
+            if let (Some(Value::Number(Number::Int(chat_id))), Some(chosen_by @ Value::Strand(_))) =
+                (inner.get("admin_chat"), inner.get("admin"))
+            {
+                if chosen_by.clone().as_string() == admin.to_lowercase() {
+                    return Ok(Some(ChatId::new(*chat_id as u32)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Set the template a chat uses for messages about an event type
+    pub async fn set_template(&self, chat: ChatId, kind: EventKind, template: &str) -> Result<()> {
+        let vars = BTreeMap::from([("template".to_string(), Value::from(template.to_string()))]);
+        self.execute_with(
+            &format!("UPDATE format:{} SET {kind} = $template", chat.to_u32()),
+            vars,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Get the template a chat uses for an event type, `None` if it uses the default
+    pub async fn get_template(&self, chat: ChatId, kind: EventKind) -> Result<Option<String>> {
+        let mut resp = self
+            .execute(&format!("SELECT {kind} FROM format:{}", chat.to_u32()))
+            .await?;
+        let resp = resp.remove(0).result?;
+
+        if let Value::Object(Object(inner)) = unwrap_array(&resp) {
+            if let Some(template @ Value::Strand(_)) = inner.get(&kind.to_string()) {
+                return Ok(Some(template.clone().as_string()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Go back to the default template for an event type, or for all if none is given
+    pub async fn reset_template(&self, chat: ChatId, kind: Option<EventKind>) -> Result<()> {
+        let chat = chat.to_u32();
+        match kind {
+            Some(kind) => self.execute(&format!("UPDATE format:{chat} SET {kind} = NONE")),
+            None => self.execute(&format!("DELETE format:{chat}")),
+        }
+        .await?;
+        Ok(())
+    }
+
+    /// Store the github token of a contact, encrypted with the key of the database
+    pub async fn set_token(&self, contact: ContactId, token: &str) -> Result<()> {
+        let vars = BTreeMap::from([("token".to_string(), Value::from(self.vault.seal(token)?))]);
+        self.execute_with(
+            &format!("UPDATE contact:{} SET token = $token", contact.to_u32()),
+            vars,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Get the github token of a contact
+    pub async fn get_token(&self, contact: ContactId) -> Result<Option<String>> {
+        let mut resp = self
+            .execute(&format!("SELECT token FROM contact:{}", contact.to_u32()))
+            .await?;
+        let resp = resp.remove(0).result?;
+
+        if let Value::Object(Object(inner)) = unwrap_array(&resp) {
+            if let Some(token @ Value::Strand(_)) = inner.get("token") {
+                return Ok(Some(self.vault.open(&token.clone().as_string())?));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Forget the github token of a contact
+    pub async fn remove_token(&self, contact: ContactId) -> Result<()> {
+        self.execute(&format!(
+            "UPDATE contact:{} SET token = NONE",
+            contact.to_u32()
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Link a contact to the github login it proved to own
+    ///
+    /// This also drops a pending link code of the contact.
+    pub async fn set_login(&self, contact: ContactId, login: &str) -> Result<()> {
+        let vars = BTreeMap::from([("login".to_string(), Value::from(login.to_lowercase()))]);
+        let id = contact.to_u32();
+        self.execute_with(
+            &format!("UPDATE contact:{id} SET contact_id = {id}, login = $login, link = NONE"),
+            vars,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Get the github login a contact is linked to
+    pub async fn get_login(&self, contact: ContactId) -> Result<Option<String>> {
+        let mut resp = self
+            .execute(&format!("SELECT login FROM contact:{}", contact.to_u32()))
+            .await?;
+        let resp = resp.remove(0).result?;
+
+        if let Value::Object(Object(inner)) = unwrap_array(&resp) {
+            if let Some(login @ Value::Strand(_)) = inner.get("login") {
+                return Ok(Some(login.clone().as_string()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Forget the github login of a contact
+    pub async fn remove_login(&self, contact: ContactId) -> Result<()> {
+        self.execute(&format!(
+            "UPDATE contact:{} SET login = NONE",
+            contact.to_u32()
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Get the contacts linked to a github login
+    pub async fn get_contacts_of(&self, login: &str) -> Result<Vec<ContactId>> {
+        let vars = BTreeMap::from([("login".to_string(), Value::from(login.to_lowercase()))]);
+        let mut resp = self
+            .execute_with("SELECT contact_id FROM contact WHERE login = $login", vars)
+            .await?;
+        let resp = resp.remove(0).result?;
+
+        if let Value::Array(arr) = resp {
+            Ok(arr
+                .into_iter()
+                .filter_map(|obj| match obj {
+                    Value::Object(Object(inner)) => match inner.get("contact_id") {
+                        Some(Value::Number(Number::Int(id))) => Some(ContactId::new(*id as u32)),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect())
+        } else {
+            bail!("Error while retrieving contacts")
+        }
+    }
+
+    /// Remember the code a contact has to publish to prove it owns a login
+    pub async fn set_link_code(&self, contact: ContactId, login: &str, code: &str) -> Result<()> {
+        let vars = BTreeMap::from([
+            ("login".to_string(), Value::from(login.to_lowercase())),
+            ("code".to_string(), Value::from(code.to_string())),
+        ]);
+        self.execute_with(
+            &format!(
+                "UPDATE contact:{} SET link = {{ login: $login, code: $code }}",
+                contact.to_u32()
+            ),
+            vars,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Get the login a contact wants to link together with the code it has to publish
+    pub async fn get_link_code(&self, contact: ContactId) -> Result<Option<(String, String)>> {
+        let mut resp = self
+            .execute(&format!(
+                "SELECT link.login AS login, link.code AS code FROM contact:{}",
+                contact.to_u32()
+            ))
+            .await?;
+        let resp = resp.remove(0).result?;
+
+        if let Value::Object(Object(inner)) = unwrap_array(&resp) {
+            if let (Some(login @ Value::Strand(_)), Some(code @ Value::Strand(_))) =
+                (inner.get("login"), inner.get("code"))
+            {
+                return Ok(Some((login.clone().as_string(), code.clone().as_string())));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Remember that a contact got the introduction to the bot
+    pub async fn set_introduced(&self, contact: ContactId) -> Result<()> {
+        self.execute(&format!(
+            "UPDATE contact:{} SET introduced = true",
+            contact.to_u32()
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Check whether a contact already got the introduction to the bot
+    pub async fn is_introduced(&self, contact: ContactId) -> Result<bool> {
+        let mut resp = self
+            .execute(&format!(
+                "SELECT introduced FROM contact:{}",
+                contact.to_u32()
+            ))
+            .await?;
+        let resp = resp.remove(0).result?;
+
+        if let Value::Object(Object(inner)) = unwrap_array(&resp) {
+            return Ok(inner.get("introduced").map_or(false, Value::is_true));
+        }
+        Ok(false)
+    }
+
+    /// Turn personal notifications of a linked contact on or off
+    pub async fn set_personal(&self, contact: ContactId, enabled: bool) -> Result<()> {
+        self.execute(&format!(
+            "UPDATE contact:{} SET personal = {enabled}",
+            contact.to_u32()
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Get the linked contacts of a login that want personal notifications
+    pub async fn get_personal_contacts(&self, login: &str) -> Result<Vec<ContactId>> {
+        let vars = BTreeMap::from([("login".to_string(), Value::from(login.to_lowercase()))]);
+        let mut resp = self
+            .execute_with(
+                "SELECT contact_id FROM contact WHERE login = $login AND personal = true",
+                vars,
+            )
+            .await?;
+        let resp = resp.remove(0).result?;
+
+        if let Value::Array(arr) = resp {
+            Ok(arr
+                .into_iter()
+                .filter_map(|obj| match obj {
+                    Value::Object(Object(inner)) => match inner.get("contact_id") {
+                        Some(Value::Number(Number::Int(id))) => Some(ContactId::new(*id as u32)),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect())
+        } else {
+            bail!("Error while retrieving contacts")
+        }
+    }
+
+    /// Check whether any linked contact wants personal notifications
+    async fn has_personal_contacts(&self) -> Result<bool> {
+        let mut resp = self
+            .execute("SELECT contact_id FROM contact WHERE login != NONE AND personal = true")
+            .await?;
+        let resp = resp.remove(0).result?;
+        Ok(matches!(resp, Value::Array(arr) if !arr.is_empty()))
+    }
+
+    /// Allow or forbid a contact to approve and merge PRs of a repository
+    pub async fn set_merge_permission(
+        &self,
+        contact: ContactId,
+        repo: usize,
+        allowed: bool,
+    ) -> Result<()> {
+        if self.may_merge(contact, repo).await? == allowed {
+            return Ok(());
+        }
+        let action = if allowed { "+=" } else { "-=" };
+        self.execute(&format!(
+            "UPDATE contact:{} SET merge_repos {action} [{repo}]",
+            contact.to_u32()
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Check whether a contact was allowed to approve and merge PRs of a repository
+    pub async fn may_merge(&self, contact: ContactId, repo: usize) -> Result<bool> {
+        let mut resp = self
+            .execute(&format!(
+                "SELECT merge_repos FROM contact:{}",
+                contact.to_u32()
+            ))
+            .await?;
+        let resp = resp.remove(0).result?;
+
+        if let Value::Object(Object(inner)) = unwrap_array(&resp) {
+            if let Some(Value::Array(repos)) = inner.get("merge_repos") {
+                return Ok(repos.iter().any(
+                    |id| matches!(id, Value::Number(Number::Int(id)) if *id as usize == repo),
+                ));
+            }
+        }
+        Ok(false)
+    }
+
+    /// Remember which issue or PR a sent notification is about
+    pub async fn add_notification(&self, msg: MsgId, repo: usize, number: usize) -> Result<()> {
+        self.execute(&format!(
+            "CREATE notification:{} SET repo = {repo}, number = {number}",
+            msg.to_u32()
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Remember the first notification a chat got about an issue or PR
+    pub async fn set_thread_start(
+        &self,
+        chat: ChatId,
+        repo: usize,
+        number: usize,
+        msg: MsgId,
+    ) -> Result<()> {
+        let chat = chat.to_u32();
+        self.execute(&format!(
+            "DELETE threads WHERE chat = {chat} AND repo = {repo} AND number = {number}; \
+             CREATE threads SET chat = {chat}, repo = {repo}, number = {number}, msg = {}",
+            msg.to_u32()
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Get the first notification a chat got about an issue or PR
+    pub async fn get_thread_start(
+        &self,
+        chat: ChatId,
+        repo: usize,
+        number: usize,
+    ) -> Result<Option<MsgId>> {
+        let mut resp = self
+            .execute(&format!(
+                "SELECT msg FROM threads WHERE chat = {} AND repo = {repo} AND number = {number}",
+                chat.to_u32()
+            ))
+            .await?;
+        let resp = resp.remove(0).result?;
+
+        if let Value::Object(Object(inner)) = unwrap_array(&resp) {
+            if let Some(Value::Number(Number::Int(msg))) = inner.get("msg") {
+                return Ok(Some(MsgId::new(*msg as u32)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Get the repository and number of the issue or PR a notification is about
+    pub async fn get_notification(&self, msg: MsgId) -> Result<Option<(usize, usize)>> {
+        let mut resp = self
+            .execute(&format!(
+                "SELECT repo, number FROM notification:{}",
+                msg.to_u32()
+            ))
+            .await?;
+        let resp = resp.remove(0).result?;
+
+        if let Value::Object(Object(inner)) = unwrap_array(&resp) {
+            if let (
+                Some(Value::Number(Number::Int(repo))),
+                Some(Value::Number(Number::Int(number))),
+            ) = (inner.get("repo"), inner.get("number"))
+            {
+                return Ok(Some((*repo as usize, *number as usize)));
+            }
+        }
+        Ok(None)
+    }
+
     /// Get the name of one repository
     pub async fn get_name(&self, id: usize) -> Result<String> {
         let mut resp = self.execute(&format!("SELECT name FROM repo:{id}")).await?;
@@ -236,7 +1097,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_repository_ids() {
-        let db = DB::new("memory").await;
+        let db = DB::new("memory", "db key").await;
         db.add_repository(Repository {
             id: 12,
             ..Default::default()
@@ -248,7 +1109,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_remove() {
-        let db = DB::new("memory").await;
+        let db = DB::new("memory", "db key").await;
         db.add_repository(Repository {
             id: 12,
             ..Default::default()
@@ -259,9 +1120,76 @@ mod tests {
         assert_eq!(db.get_repository_ids().await.unwrap(), [] as [usize; 0]);
     }
 
+    #[tokio::test]
+    async fn test_remove_leaves_nothing_behind() {
+        let db = DB::new("memory", "db key").await;
+        let repo = || Repository {
+            id: 12,
+            ..Default::default()
+        };
+        db.add_repository(repo()).await.unwrap();
+        let chat = ChatId::new(10);
+        db.add_subscriber(12, "pr_opened", chat).await.unwrap();
+        db.set_thread_start(chat, 12, 3, MsgId::new(20))
+            .await
+            .unwrap();
+        db.add_notification(MsgId::new(20), 12, 3).await.unwrap();
+        db.set_merge_permission(ContactId::new(11), 12, true)
+            .await
+            .unwrap();
+
+        db.remove_repository(12).await.unwrap();
+        db.add_repository(repo()).await.unwrap();
+        assert_eq!(
+            db.get_subscriptions(chat).await.unwrap(),
+            [] as [(usize, String); 0]
+        );
+        assert_eq!(
+            db.get_subscribers(12, "pr", "opened").await.unwrap(),
+            [] as [ChatId; 0]
+        );
+        assert_eq!(db.get_thread_start(chat, 12, 3).await.unwrap(), None);
+        assert_eq!(db.get_notification(MsgId::new(20)).await.unwrap(), None);
+        assert!(!db.may_merge(ContactId::new(11), 12).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_repository() {
+        let db = DB::new("memory", "db key").await;
+        for (id, owner) in [(12, "deltachat"), (13, "septias")] {
+            db.add_repository(Repository {
+                id,
+                owner,
+                name: "github-bot",
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        }
+        assert_eq!(db.resolve_repository("12").await.unwrap(), 12);
+        assert_eq!(
+            db.resolve_repository("Septias/github-bot").await.unwrap(),
+            13
+        );
+        assert!(matches!(
+            db.resolve_repository("github-bot")
+                .await
+                .unwrap_err()
+                .downcast_ref::<RepoError>(),
+            Some(RepoError::Ambiguous(..))
+        ));
+        assert!(matches!(
+            db.resolve_repository("14")
+                .await
+                .unwrap_err()
+                .downcast_ref::<RepoError>(),
+            Some(RepoError::NotFound(..))
+        ));
+    }
+
     #[tokio::test]
     async fn test_get_hook_id() {
-        let db = DB::new("memory").await;
+        let db = DB::new("memory", "db key").await;
         db.add_repository(Repository {
             hook_id: 23,
             id: 12,
@@ -275,7 +1203,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_owner() {
-        let db = DB::new("memory").await;
+        let db = DB::new("memory", "db key").await;
         db.add_repository(Repository {
             owner: "Me",
             id: 12,
@@ -286,9 +1214,439 @@ mod tests {
         assert_eq!(db.get_owner(12).await.unwrap(), "Me".to_string());
     }
 
+    #[tokio::test]
+    async fn test_add_repository_quotes() {
+        let db = DB::new("memory", "db key").await;
+        db.add_repository(Repository {
+            owner: "it's me",
+            name: "x', api_key = 'stolen",
+            api_key: "ghp_'\"",
+            id: 12,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(db.get_owner(12).await.unwrap(), "it's me");
+        assert_eq!(db.get_name(12).await.unwrap(), "x', api_key = 'stolen");
+        assert_eq!(
+            db.get_api_key(12).await.unwrap().as_deref(),
+            Some("ghp_'\"")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_subscribed_events() {
+        let db = DB::new("memory", "db key").await;
+        db.add_repository(Repository {
+            id: 12,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(db.get_subscribed_events(12).await.unwrap(), [] as [&str; 0]);
+
+        db.add_subscriber(12, "pr_opened", ChatId::new(10))
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_subscribed_events(12).await.unwrap(),
+            ["pull_request"]
+        );
+
+        db.set_events(12, &["pull_request"]).await.unwrap();
+        assert_eq!(db.get_events(12).await.unwrap(), ["pull_request"]);
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions() {
+        let db = DB::new("memory", "db key").await;
+        for id in [12, 13] {
+            db.add_repository(Repository {
+                id,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        }
+        let chat = ChatId::new(10);
+        db.add_subscriber(12, "pr_opened", chat).await.unwrap();
+        db.add_subscriber(13, "issue_closed", chat).await.unwrap();
+        db.add_subscriber(13, "issue_opened", ChatId::new(11))
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_subscriptions(chat).await.unwrap(),
+            [
+                (12, "pr_opened".to_string()),
+                (13, "issue_closed".to_string())
+            ]
+        );
+
+        assert_eq!(
+            db.remove_all_subscriptions(13, chat).await.unwrap(),
+            ["issue_closed"]
+        );
+        assert_eq!(
+            db.get_subscriptions(chat).await.unwrap(),
+            [(12, "pr_opened".to_string())]
+        );
+        assert_eq!(db.get_subscribed_events(13).await.unwrap(), ["issues"]);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_subscriber() {
+        let db = DB::new("memory", "db key").await;
+        let chat = ChatId::new(10);
+        assert!(db.add_subscriber(12, "pr_opened", chat).await.unwrap());
+        assert!(!db.add_subscriber(12, "pr_opened", chat).await.unwrap());
+        assert_eq!(
+            db.get_subscribers(12, "pr", "opened").await.unwrap(),
+            [chat]
+        );
+        assert!(db.remove_subscriber(12, "pr_opened", chat).await.unwrap());
+        assert!(!db.remove_subscriber(12, "pr_opened", chat).await.unwrap());
+        assert!(!db
+            .remove_subscriber(12, "issue_opened", chat)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_wildcard_subscribers() {
+        let db = DB::new("memory", "db key").await;
+        db.add_subscriber(12, "pr_opened", ChatId::new(10))
+            .await
+            .unwrap();
+        db.add_subscriber(12, "pr_all", ChatId::new(11))
+            .await
+            .unwrap();
+        db.add_subscriber(12, "all", ChatId::new(12)).await.unwrap();
+        db.add_subscriber(12, "all", ChatId::new(10)).await.unwrap();
+
+        assert_eq!(
+            db.get_subscribers(12, "pr", "opened").await.unwrap(),
+            [ChatId::new(12), ChatId::new(10), ChatId::new(11)]
+        );
+        assert_eq!(
+            db.get_subscribers(12, "pr", "closed").await.unwrap(),
+            [ChatId::new(12), ChatId::new(10), ChatId::new(11)]
+        );
+        assert_eq!(
+            db.get_subscribers(12, "issue", "closed").await.unwrap(),
+            [ChatId::new(12), ChatId::new(10)]
+        );
+        assert_eq!(
+            db.get_subscribed_events(12).await.unwrap(),
+            ["issues", "pull_request"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_filters() {
+        let db = DB::new("memory", "db key").await;
+        let (chat, other) = (ChatId::new(10), ChatId::new(11));
+        let filter = Filter {
+            labels: vec!["bug".to_string()],
+            authors: vec!["!dependabot[bot]".to_string()],
+            ..Default::default()
+        };
+        db.add_subscriber(12, "pr_opened", chat).await.unwrap();
+        db.add_subscriber(12, "all", chat).await.unwrap();
+        db.add_subscriber(12, "pr_opened", other).await.unwrap();
+        assert!(db.set_filter(12, "pr_opened", chat, &filter).await.unwrap());
+        assert!(!db.set_filter(12, "pr_opened", chat, &filter).await.unwrap());
+
+        assert_eq!(db.get_filter(12, "pr_opened", chat).await.unwrap(), filter);
+        assert!(db.get_filter(12, "all", chat).await.unwrap().is_empty());
+        assert_eq!(
+            db.get_subscriptions_to(12, "pr", "opened").await.unwrap(),
+            [
+                (chat, Filter::default()),
+                (chat, filter.clone()),
+                (other, Filter::default())
+            ]
+        );
+
+        db.remove_subscriber(12, "pr_opened", chat).await.unwrap();
+        assert!(db
+            .get_filter(12, "pr_opened", chat)
+            .await
+            .unwrap()
+            .is_empty());
+        db.add_subscriber(12, "pr_opened", chat).await.unwrap();
+        db.set_filter(12, "pr_opened", chat, &filter).await.unwrap();
+        db.remove_all_subscriptions(12, chat).await.unwrap();
+        assert!(db
+            .get_filter(12, "pr_opened", chat)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failing() {
+        let db = DB::new("memory", "db key").await;
+        db.add_repository(Repository {
+            id: 12,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert!(!db.is_failing(12).await.unwrap());
+        db.set_failing(12, true).await.unwrap();
+        assert!(db.is_failing(12).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_poll_cursor() {
+        let db = DB::new("memory", "db key").await;
+        db.add_repository(Repository {
+            id: 12,
+            polling: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert!(db.is_polling(12).await.unwrap());
+        assert_eq!(db.get_poll_cursor(12).await.unwrap(), PollCursor::default());
+
+        let cursor = PollCursor {
+            etag: Some(r#"W/"a18c3bded88eb5dbb5c849a489412bf3""#.to_string()),
+            since: Some(26170256924),
+            updated: Some("2023-01-26T12:01:51Z".to_string()),
+        };
+        db.set_poll_cursor(12, &cursor).await.unwrap();
+        assert_eq!(db.get_poll_cursor(12).await.unwrap(), cursor);
+    }
+
+    #[tokio::test]
+    async fn test_templates() {
+        let db = DB::new("memory", "db key").await;
+        let chat = ChatId::new(10);
+        assert_eq!(db.get_template(chat, EventKind::Pr).await.unwrap(), None);
+        let template = "{{ sender.login }}: '{{ pull_request.title }}'";
+        db.set_template(chat, EventKind::Pr, template)
+            .await
+            .unwrap();
+        db.set_template(chat, EventKind::Push, "pushed")
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_template(chat, EventKind::Pr)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some(template)
+        );
+        assert_eq!(
+            db.get_template(ChatId::new(11), EventKind::Pr)
+                .await
+                .unwrap(),
+            None
+        );
+
+        db.reset_template(chat, Some(EventKind::Pr)).await.unwrap();
+        assert_eq!(db.get_template(chat, EventKind::Pr).await.unwrap(), None);
+        assert!(db
+            .get_template(chat, EventKind::Push)
+            .await
+            .unwrap()
+            .is_some());
+        db.reset_template(chat, None).await.unwrap();
+        assert_eq!(db.get_template(chat, EventKind::Push).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_tokens() {
+        let db = DB::new("memory", "db key").await;
+        let contact = ContactId::new(10);
+        assert_eq!(db.get_token(contact).await.unwrap(), None);
+        db.set_token(contact, "ghp_xyz").await.unwrap();
+        assert_eq!(
+            db.get_token(contact).await.unwrap().as_deref(),
+            Some("ghp_xyz")
+        );
+        assert_eq!(db.get_token(ContactId::new(11)).await.unwrap(), None);
+        db.remove_token(contact).await.unwrap();
+        assert_eq!(db.get_token(contact).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_seal_plaintext_secrets() {
+        let db = DB::new("memory", "db key").await;
+        db.execute(
+            "UPDATE contact:10 SET token = 'ghp_contact'; CREATE repo:12 SET api_key = 'ghp_repo'",
+        )
+        .await
+        .unwrap();
+        db.seal_plaintext_secrets().await.unwrap();
+        // sealing twice must not seal the sealed values again
+        db.seal_plaintext_secrets().await.unwrap();
+
+        let mut resp = db.execute("SELECT token FROM contact:10").await.unwrap();
+        let stored = resp.remove(0).result.unwrap().to_string();
+        assert!(!stored.contains("ghp_contact"));
+        assert_eq!(
+            db.get_token(ContactId::new(10)).await.unwrap().as_deref(),
+            Some("ghp_contact")
+        );
+        assert_eq!(
+            db.get_api_key(12).await.unwrap().as_deref(),
+            Some("ghp_repo")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_logins() {
+        let db = DB::new("memory", "db key").await;
+        let contact = ContactId::new(10);
+        assert_eq!(db.get_login(contact).await.unwrap(), None);
+        db.set_link_code(contact, "Septias", "dcbot-123")
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_link_code(contact).await.unwrap(),
+            Some(("septias".to_string(), "dcbot-123".to_string()))
+        );
+
+        db.set_login(contact, "Septias").await.unwrap();
+        db.set_login(ContactId::new(11), "septias").await.unwrap();
+        db.set_login(ContactId::new(12), "hpk42").await.unwrap();
+        assert_eq!(db.get_link_code(contact).await.unwrap(), None);
+        assert_eq!(
+            db.get_login(contact).await.unwrap().as_deref(),
+            Some("septias")
+        );
+        let mut contacts = db.get_contacts_of("Septias").await.unwrap();
+        contacts.sort_unstable_by_key(|contact| contact.to_u32());
+        assert_eq!(contacts, [ContactId::new(10), ContactId::new(11)]);
+
+        db.remove_login(contact).await.unwrap();
+        assert_eq!(db.get_login(contact).await.unwrap(), None);
+        assert_eq!(
+            db.get_contacts_of("septias").await.unwrap(),
+            [ContactId::new(11)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_introduced() {
+        let db = DB::new("memory", "db key").await;
+        let contact = ContactId::new(10);
+        assert!(!db.is_introduced(contact).await.unwrap());
+        db.set_introduced(contact).await.unwrap();
+        assert!(db.is_introduced(contact).await.unwrap());
+        assert!(!db.is_introduced(ContactId::new(11)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_personal() {
+        let db = DB::new("memory", "db key").await;
+        let contact = ContactId::new(10);
+        db.set_login(contact, "Septias").await.unwrap();
+        db.set_login(ContactId::new(11), "septias").await.unwrap();
+        assert!(db
+            .get_personal_contacts("septias")
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(db.get_subscribed_events(12).await.unwrap().is_empty());
+
+        db.set_personal(contact, true).await.unwrap();
+        assert_eq!(
+            db.get_personal_contacts("Septias").await.unwrap(),
+            [contact]
+        );
+        assert_eq!(
+            db.get_subscribed_events(12).await.unwrap(),
+            ["issue_comment", "issues", "pull_request"]
+        );
+        db.set_personal(contact, false).await.unwrap();
+        assert!(db
+            .get_personal_contacts("septias")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_merge_permission() {
+        let db = DB::new("memory", "db key").await;
+        let contact = ContactId::new(10);
+        assert!(!db.may_merge(contact, 12).await.unwrap());
+        db.set_merge_permission(contact, 12, true).await.unwrap();
+        db.set_merge_permission(contact, 12, true).await.unwrap();
+        db.set_merge_permission(contact, 13, true).await.unwrap();
+        assert!(db.may_merge(contact, 12).await.unwrap());
+        assert!(!db.may_merge(ContactId::new(11), 12).await.unwrap());
+        db.set_merge_permission(contact, 12, false).await.unwrap();
+        assert!(!db.may_merge(contact, 12).await.unwrap());
+        assert!(db.may_merge(contact, 13).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_notifications() {
+        let db = DB::new("memory", "db key").await;
+        db.add_notification(MsgId::new(20), 12, 42).await.unwrap();
+        assert_eq!(
+            db.get_notification(MsgId::new(20)).await.unwrap(),
+            Some((12, 42))
+        );
+        assert_eq!(db.get_notification(MsgId::new(21)).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_threads() {
+        let db = DB::new("memory", "db key").await;
+        let chat = ChatId::new(10);
+        assert_eq!(db.get_thread_start(chat, 12, 42).await.unwrap(), None);
+        db.set_thread_start(chat, 12, 42, MsgId::new(20))
+            .await
+            .unwrap();
+        db.set_thread_start(chat, 12, 43, MsgId::new(21))
+            .await
+            .unwrap();
+        db.set_thread_start(ChatId::new(11), 12, 42, MsgId::new(22))
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_thread_start(chat, 12, 42).await.unwrap(),
+            Some(MsgId::new(20))
+        );
+        db.set_thread_start(chat, 12, 42, MsgId::new(23))
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_thread_start(chat, 12, 42).await.unwrap(),
+            Some(MsgId::new(23))
+        );
+        assert_eq!(db.get_thread_start(chat, 13, 42).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_admin_chat() {
+        let db = DB::new("memory", "db key").await;
+        let admin = "admin@example.org";
+        assert_eq!(db.get_admin_chat(admin).await.unwrap(), None);
+        db.set_admin_chat(ChatId::new(10), "Admin@example.org")
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_admin_chat(admin).await.unwrap(),
+            Some(ChatId::new(10))
+        );
+        assert_eq!(db.get_admin_chat("other@example.org").await.unwrap(), None);
+
+        // chats chosen before the admin was configured don't count
+        db.execute("UPDATE config:bot SET admin_chat = 11, admin = NONE")
+            .await
+            .unwrap();
+        assert_eq!(db.get_admin_chat(admin).await.unwrap(), None);
+    }
+
     #[tokio::test]
     async fn test_get_name() {
-        let db = DB::new("memory").await;
+        let db = DB::new("memory", "db key").await;
         db.add_repository(Repository {
             name: "ligma",
             id: 12,
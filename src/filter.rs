@@ -0,0 +1,304 @@
+//! Filters that narrow down which events of a subscription get delivered
+
+use clap::Args;
+use globset::{GlobBuilder, GlobMatcher};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+use crate::shared::{issue::IssueEvent, pr::PREvent, push::PushEvent};
+
+/// Optional conditions of a subscription
+///
+/// Values starting with `!` exclude events that match them. Of the other values
+/// at least one has to match.
+#[derive(Args, PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Filter {
+    /// Only issues and PRs with this label
+    #[arg(long = "label", global = true)]
+    pub labels: Vec<String>,
+
+    /// Only events triggered by this user
+    #[arg(long = "author", global = true)]
+    pub authors: Vec<String>,
+
+    /// Only issues and PRs assigned to this user
+    #[arg(long = "assignee", global = true)]
+    pub assignees: Vec<String>,
+
+    /// Only PRs against and pushes to this branch
+    #[arg(long = "base", global = true)]
+    pub bases: Vec<String>,
+
+    /// Only PRs and pushes changing a file matching this glob, e.g. `web/**`
+    #[arg(long = "path", global = true, value_parser = parse_glob)]
+    pub paths: Vec<String>,
+
+    /// Leave out events of draft PRs
+    #[arg(long = "no-drafts", global = true)]
+    pub no_drafts: bool,
+}
+
+/// The properties of an event that filters are checked against
+///
+/// Properties the event doesn't have are `None` and ignored by filters.
+pub struct Subject<'a> {
+    pub labels: Option<Vec<&'a str>>,
+    pub sender: &'a str,
+    pub assignees: Option<Vec<&'a str>>,
+    pub base: Option<&'a str>,
+    pub paths: Option<Vec<&'a str>>,
+    pub draft: Option<bool>,
+}
+
+impl<'a> From<&'a IssueEvent> for Subject<'a> {
+    fn from(event: &'a IssueEvent) -> Self {
+        Self {
+            labels: Some(event.issue.labels.iter().map(|l| l.name.as_str()).collect()),
+            sender: &event.sender.login,
+            assignees: Some(
+                event
+                    .issue
+                    .assignees
+                    .iter()
+                    .map(|u| u.login.as_str())
+                    .collect(),
+            ),
+            base: None,
+            paths: None,
+            draft: None,
+        }
+    }
+}
+
+/// The changed files of a PR aren't part of the event and have to be added separately
+impl<'a> From<&'a PREvent> for Subject<'a> {
+    fn from(event: &'a PREvent) -> Self {
+        Self {
+            labels: Some(
+                event
+                    .pull_request
+                    .labels
+                    .iter()
+                    .map(|l| l.name.as_str())
+                    .collect(),
+            ),
+            sender: &event.sender.login,
+            assignees: Some(
+                event
+                    .pull_request
+                    .assignees
+                    .iter()
+                    .map(|u| u.login.as_str())
+                    .collect(),
+            ),
+            base: Some(&event.pull_request.base.name),
+            paths: None,
+            draft: Some(event.pull_request.draft),
+        }
+    }
+}
+
+impl<'a> From<&'a PushEvent> for Subject<'a> {
+    fn from(event: &'a PushEvent) -> Self {
+        Self {
+            labels: None,
+            sender: &event.sender.login,
+            assignees: None,
+            base: Some(event.branch()),
+            paths: Some(event.paths()),
+            draft: None,
+        }
+    }
+}
+
+impl Filter {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Check whether an event passes the filter
+    pub fn matches(&self, subject: &Subject) -> bool {
+        let applies = |patterns: &[String], values: Option<&[&str]>| {
+            values.map_or(true, |values| matches_values(patterns, values))
+        };
+        applies(&self.labels, subject.labels.as_deref())
+            && matches_values(&self.authors, &[subject.sender])
+            && applies(&self.assignees, subject.assignees.as_deref())
+            && applies(&self.bases, subject.base.as_ref().map(std::slice::from_ref))
+            && subject
+                .paths
+                .as_ref()
+                .map_or(true, |paths| matches_paths(&self.paths, paths))
+            && !(self.no_drafts && subject.draft == Some(true))
+    }
+}
+
+/// Check values against patterns where `!value` excludes a value
+fn matches_values(patterns: &[String], values: &[&str]) -> bool {
+    let contains = |pattern: &str| values.iter().any(|v| v.eq_ignore_ascii_case(pattern));
+    let (excluded, required): (Vec<_>, Vec<_>) = patterns
+        .iter()
+        .partition(|pattern| pattern.starts_with('!'));
+    excluded.iter().all(|pattern| !contains(&pattern[1..]))
+        && (required.is_empty() || required.iter().any(|pattern| contains(pattern)))
+}
+
+/// Check whether one of the paths matches the globs and isn't excluded by a `!glob`
+fn matches_paths(patterns: &[String], paths: &[&str]) -> bool {
+    if patterns.is_empty() {
+        return true;
+    }
+    let (excluded, required): (Vec<_>, Vec<_>) = patterns
+        .iter()
+        .filter_map(|pattern| match pattern.strip_prefix('!') {
+            Some(pattern) => glob(pattern).map(|glob| (true, glob)),
+            None => glob(pattern).map(|glob| (false, glob)),
+        })
+        .partition(|(excluded, _)| *excluded);
+    paths.iter().any(|path| {
+        excluded.iter().all(|(_, glob)| !glob.is_match(path))
+            && (required.is_empty() || required.iter().any(|(_, glob)| glob.is_match(path)))
+    })
+}
+
+/// Compile a glob in which `*` doesn't cross directories but `**` does
+fn glob(pattern: &str) -> Option<GlobMatcher> {
+    GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .ok()
+        .map(|glob| glob.compile_matcher())
+}
+
+fn parse_glob(pattern: &str) -> Result<String, String> {
+    match glob(pattern.strip_prefix('!').unwrap_or(pattern)) {
+        Some(_) => Ok(pattern.to_string()),
+        None => Err(format!("`{pattern}` is not a valid glob")),
+    }
+}
+
+impl Display for Filter {
+    /// Formats the filter as command line options
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let options = [
+            ("label", &self.labels),
+            ("author", &self.authors),
+            ("assignee", &self.assignees),
+            ("base", &self.bases),
+            ("path", &self.paths),
+        ];
+        let mut first = true;
+        for (option, values) in options {
+            for value in values {
+                if !first {
+                    write!(f, " ")?;
+                }
+                write!(f, "--{option} {value}")?;
+                first = false;
+            }
+        }
+        if self.no_drafts {
+            write!(f, "{}--no-drafts", if first { "" } else { " " })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subject<'a>(labels: Vec<&'a str>, sender: &'a str) -> Subject<'a> {
+        Subject {
+            labels: Some(labels),
+            sender,
+            assignees: Some(vec!["septias"]),
+            base: Some("main"),
+            paths: None,
+            draft: Some(false),
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let filter = Filter {
+            labels: vec!["bug".to_string()],
+            authors: vec!["!dependabot[bot]".to_string()],
+            ..Default::default()
+        };
+        assert!(filter.matches(&subject(vec!["Bug", "ui"], "septias")));
+        assert!(!filter.matches(&subject(vec!["ui"], "septias")));
+        assert!(!filter.matches(&subject(vec!["bug"], "dependabot[bot]")));
+        assert!(Filter::default().matches(&subject(vec![], "dependabot[bot]")));
+
+        let filter = Filter {
+            bases: vec!["main".to_string()],
+            assignees: vec!["septias".to_string(), "hpk42".to_string()],
+            ..Default::default()
+        };
+        assert!(filter.matches(&subject(vec![], "septias")));
+        let mut other_base = subject(vec![], "septias");
+        other_base.base = Some("stable");
+        assert!(!filter.matches(&other_base));
+        other_base.base = None;
+        assert!(filter.matches(&other_base));
+    }
+
+    #[test]
+    fn test_matches_paths() {
+        let filter = Filter {
+            paths: vec!["web/**".to_string(), "!**/*.md".to_string()],
+            labels: vec!["bug".to_string()],
+            ..Default::default()
+        };
+        let mut push = subject(vec![], "septias");
+        push.labels = None;
+        push.paths = Some(vec!["readme.md", "web/index.html"]);
+        assert!(filter.matches(&push));
+        push.paths = Some(vec!["readme.md", "web/readme.md", "src/main.rs"]);
+        assert!(!filter.matches(&push));
+        push.paths = Some(vec![]);
+        assert!(!filter.matches(&push));
+
+        let filter = Filter {
+            paths: vec!["src/*.rs".to_string()],
+            ..Default::default()
+        };
+        push.paths = Some(vec!["src/bin/main.rs"]);
+        assert!(!filter.matches(&push));
+        push.paths = None;
+        assert!(filter.matches(&push));
+        assert!(parse_glob("!web/[").is_err());
+    }
+
+    #[test]
+    fn test_no_drafts() {
+        let filter = Filter {
+            no_drafts: true,
+            ..Default::default()
+        };
+        let mut pr = subject(vec![], "septias");
+        assert!(filter.matches(&pr));
+        pr.draft = Some(true);
+        assert!(!filter.matches(&pr));
+        assert!(Filter::default().matches(&pr));
+        pr.draft = None;
+        assert!(filter.matches(&pr));
+    }
+
+    #[test]
+    fn test_display() {
+        let filter = Filter {
+            labels: vec!["bug".to_string()],
+            authors: vec!["!dependabot[bot]".to_string()],
+            paths: vec!["web/**".to_string()],
+            no_drafts: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            filter.to_string(),
+            "--label bug --author !dependabot[bot] --path web/** --no-drafts"
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Health checks for repository webhooks
+
+use itertools::Itertools;
+
+use crate::rest_api::{get_deliveries, get_hook, Delivery, Hook};
+
+/// State of a webhook and its recent deliveries
+pub struct HookHealth {
+    pub hook: Hook,
+    /// Failed deliveries that haven't been redelivered successfully
+    pub failed: Vec<Delivery>,
+    /// Whether the hook should be active because chats are subscribed to it
+    pub expect_active: bool,
+}
+
+impl HookHealth {
+    /// Fetch the hook and its deliveries from github
+    pub async fn check(
+        owner: &str,
+        repo: &str,
+        hook_id: usize,
+        key: &str,
+        expect_active: bool,
+    ) -> anyhow::Result<Self> {
+        let hook = get_hook(owner, repo, hook_id, key).await?;
+        let deliveries = get_deliveries(owner, repo, hook_id, key).await?;
+        Ok(Self {
+            hook,
+            failed: unresolved_failures(deliveries),
+            expect_active,
+        })
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.problems().is_empty()
+    }
+
+    /// Chat message describing the health of the hook of a repository
+    pub fn report(&self, repo: &str) -> String {
+        let problems = self.problems();
+        if problems.is_empty() {
+            format!("The webhook of {repo} is healthy")
+        } else {
+            format!(
+                "The webhook of {repo} is failing:\n{}",
+                problems
+                    .iter()
+                    .map(|problem| format!("- {problem}"))
+                    .join("\n")
+            )
+        }
+    }
+
+    /// Human readable list of everything that is wrong with the hook
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.expect_active && !self.hook.active {
+            problems.push("the webhook is disabled".to_string());
+        }
+        if let Some(response) = &self.hook.last_response {
+            if let Some(code) = response.code.filter(|code| !(200..300).contains(code)) {
+                problems.push(format!(
+                    "the last delivery was answered with {code}: {}",
+                    response.message.as_deref().unwrap_or("no message")
+                ));
+            }
+        }
+        if !self.failed.is_empty() {
+            problems.push(format!(
+                "{} deliveries failed:\n{}",
+                self.failed.len(),
+                self.failed
+                    .iter()
+                    .map(|delivery| format!(
+                        "  {} {} at {} ({})",
+                        delivery.event,
+                        delivery.action.as_deref().unwrap_or_default(),
+                        delivery.delivered_at,
+                        delivery.status
+                    ))
+                    .join("\n")
+            ));
+        }
+        problems
+    }
+}
+
+/// Filter failed deliveries which never succeeded in a later attempt
+fn unresolved_failures(deliveries: Vec<Delivery>) -> Vec<Delivery> {
+    let resolved = deliveries
+        .iter()
+        .filter(|delivery| delivery.succeeded())
+        .map(|delivery| delivery.guid.clone())
+        .collect::<Vec<_>>();
+    deliveries
+        .into_iter()
+        .filter(|delivery| !delivery.succeeded() && !resolved.contains(&delivery.guid))
+        .unique_by(|delivery| delivery.guid.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delivery(id: usize, guid: &str, status_code: u16) -> Delivery {
+        Delivery {
+            id,
+            guid: guid.to_string(),
+            delivered_at: "2022-11-02T23:03:24Z".to_string(),
+            redelivery: false,
+            status: String::new(),
+            status_code,
+            event: "issues".to_string(),
+            action: Some("opened".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_unresolved_failures() {
+        let failed = unresolved_failures(vec![
+            delivery(1, "a", 502),
+            delivery(2, "a", 200),
+            delivery(3, "b", 200),
+            delivery(4, "c", 0),
+            delivery(5, "c", 502),
+        ]);
+        assert_eq!(failed.iter().map(|d| d.id).collect::<Vec<_>>(), [4]);
+    }
+}
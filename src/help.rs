@@ -0,0 +1,349 @@
+//! Chat friendly help generated from the command definitions
+//!
+//! Clap's own help is laid out for terminals, in a chat bubble short lines read better.
+
+use clap::{error::ErrorKind, Arg, ArgAction, Command, CommandFactory};
+use itertools::Itertools;
+
+use crate::parser::Cli;
+
+/// Introduction for contacts writing to the bot for the first time
+pub fn introduction() -> String {
+    format!(
+        "Hi! I forward events of github repositories to chats and let you work with \
+         issues and pull requests from here. Commands start with `gh`, for example:\n\n\
+         gh subscribe septias/github-bot pr opened\n\n{}",
+        overview()
+    )
+}
+
+/// Help for the words after `gh help`, which name a command and its subcommands
+pub fn help(words: &[&str]) -> String {
+    let root = Cli::command();
+    let (command, path) = find(&root, words);
+    if path.is_empty() {
+        overview()
+    } else {
+        describe(command, &path)
+    }
+}
+
+/// Answer to a command that couldn't be parsed, `words` are the ones after `gh`
+pub fn error_reply(words: &[&str], err: &clap::Error) -> String {
+    match err.kind() {
+        ErrorKind::DisplayHelp
+        | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+        | ErrorKind::MissingSubcommand => help(words),
+        ErrorKind::InvalidSubcommand => {
+            let root = Cli::command();
+            let (command, path) = find(&root, words);
+            let unknown = words.get(path.len()).copied().unwrap_or_default();
+            let mut text = format!("Unknown command `{}`", command_line(&path, unknown));
+            if let Some(similar) = suggestion(command, unknown) {
+                text.push_str(&format!(
+                    ", did you mean `{}`?",
+                    command_line(&path, similar)
+                ));
+            }
+            text.push_str(&format!("\n\n{}", help(&path)));
+            text
+        }
+        _ => {
+            let root = Cli::command();
+            let (command, path) = find(&root, words);
+            let error = err.to_string();
+            let error = error.lines().next().unwrap_or_default();
+            let error = error.strip_prefix("error: ").unwrap_or(error);
+            format!(
+                "{error}\n\nUsage: {}\nSend `gh help {}` for details",
+                usage(command, &path),
+                path.join(" ")
+            )
+        }
+    }
+}
+
+/// All commands with a line about each of them
+fn overview() -> String {
+    let commands = Cli::command()
+        .get_subcommands()
+        .filter(|command| !command.is_hide_set())
+        .map(|command| summary(&[], command))
+        .join("\n");
+    format!("Commands:\n{commands}\n\nSend `gh help <command>` to learn more about one of them")
+}
+
+/// Usage, arguments, subcommands and examples of a command
+fn describe(command: &Command, path: &[&str]) -> String {
+    let mut text = format!("{}\n\nUsage: {}", about(command), usage(command, path));
+
+    let arguments = arguments(command)
+        .filter_map(|arg| {
+            let help = arg.get_help()?;
+            Some(format!("{}: {help}", argument(arg)))
+        })
+        .join("\n");
+    if !arguments.is_empty() {
+        text.push_str(&format!("\n\n{arguments}"));
+    }
+
+    let subcommands = command
+        .get_subcommands()
+        .filter(|command| !command.is_hide_set())
+        .map(|subcommand| summary(path, subcommand))
+        .join("\n");
+    if !subcommands.is_empty() {
+        text.push_str(&format!("\n\nSubcommands:\n{subcommands}"));
+    }
+
+    let examples = examples(path).join("\n");
+    if !examples.is_empty() {
+        text.push_str(&format!("\n\nExamples:\n{examples}"));
+    }
+    text
+}
+
+/// Examples of the command at `path` and its subcommands, wherever they are attached
+fn examples(path: &[&str]) -> Vec<String> {
+    let root = Cli::command();
+    attached_examples(&root)
+        .into_iter()
+        .filter(|example| {
+            let words = example.split(' ').skip(1).collect::<Vec<_>>();
+            find(&root, &words).1.starts_with(path)
+        })
+        .collect()
+}
+
+/// Examples attached with `after_help` to a command and its subcommands
+fn attached_examples(command: &Command) -> Vec<String> {
+    own_examples(command)
+        .into_iter()
+        .chain(command.get_subcommands().flat_map(attached_examples))
+        .collect()
+}
+
+fn own_examples(command: &Command) -> Vec<String> {
+    command
+        .get_after_help()
+        .map(|examples| {
+            examples
+                .to_string()
+                .lines()
+                .map(ToString::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// One line usage like `gh issue new <repo> [--label <labels>]... <text>...`
+fn usage(command: &Command, path: &[&str]) -> String {
+    let mut parts = vec![format!("gh {}", path.join(" "))];
+    let (positionals, options): (Vec<_>, Vec<_>) =
+        arguments(command).partition(|arg| arg.is_positional());
+    for arg in options {
+        parts.push(format!("[{}]", argument(arg)));
+    }
+    for arg in positionals {
+        parts.push(if arg.is_required_set() {
+            argument(arg)
+        } else {
+            format!("[{}]", argument(arg))
+        });
+    }
+    if command.has_subcommands() {
+        parts.push("<subcommand>".to_string());
+    }
+    parts.join(" ")
+}
+
+/// An argument like `<repo>`, `--label <labels>...` or `--squash`
+fn argument(arg: &Arg) -> String {
+    let name = arg.get_id().as_str();
+    let multiple = if matches!(arg.get_action(), ArgAction::Append) {
+        "..."
+    } else {
+        ""
+    };
+    match (arg.get_long(), arg.get_action()) {
+        (Some(long), ArgAction::SetTrue) => format!("--{long}"),
+        (Some(long), _) => format!("--{long} <{name}>{multiple}"),
+        (None, _) => format!("<{name}>{multiple}"),
+    }
+}
+
+/// Arguments shown in the help, without the builtin ones
+fn arguments(command: &Command) -> impl Iterator<Item = &Arg> {
+    command
+        .get_arguments()
+        .filter(|arg| !arg.is_hide_set() && !["help", "version"].contains(&arg.get_id().as_str()))
+}
+
+/// A subcommand with what it does, like `gh issue new - Open an issue`
+fn summary(path: &[&str], command: &Command) -> String {
+    let line = command_line(path, command.get_name());
+    match about(command) {
+        about if about.is_empty() => line,
+        about => format!("{line} - {about}"),
+    }
+}
+
+fn about(command: &Command) -> String {
+    command
+        .get_about()
+        .map(ToString::to_string)
+        .unwrap_or_default()
+}
+
+/// The deepest command the words lead to, and the names of the commands on the way
+fn find<'a, 'w>(root: &'a Command, words: &[&'w str]) -> (&'a Command, Vec<&'w str>) {
+    let mut command = root;
+    let mut path = vec![];
+    for word in words {
+        match command.find_subcommand(word) {
+            Some(subcommand) => {
+                command = subcommand;
+                path.push(*word);
+            }
+            None => break,
+        }
+    }
+    (command, path)
+}
+
+fn command_line(path: &[&str], name: &str) -> String {
+    path.iter()
+        .chain([&name])
+        .filter(|word| !word.is_empty())
+        .fold("gh".to_string(), |line, word| format!("{line} {word}"))
+}
+
+/// The subcommand whose name is closest to an unknown one, if any is close enough
+fn suggestion<'a>(command: &'a Command, unknown: &str) -> Option<&'a str> {
+    command
+        .get_subcommands()
+        .filter(|command| !command.is_hide_set())
+        .map(|command| command.get_name())
+        .map(|name| (name, distance(name, &unknown.to_lowercase())))
+        .filter(|(name, distance)| *distance <= name.len() / 3 + 1)
+        .min_by_key(|(_, distance)| *distance)
+        .map(|(name, _)| name)
+}
+
+/// Number of inserted, removed or replaced characters between two words
+fn distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let replaced = previous + usize::from(ca != *cb);
+            previous = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(previous + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Commands;
+    use clap::Parser;
+
+    fn reply(text: &str) -> String {
+        let words = text.split(' ').collect::<Vec<_>>();
+        let err = Cli::try_parse_from(&words).err().unwrap();
+        error_reply(&words[1..], &err)
+    }
+
+    #[test]
+    fn test_help() {
+        let overview = help(&[]);
+        assert!(overview.contains("gh subscribe - Subscribe to an event"));
+        assert!(overview.contains("gh help - "));
+        assert_eq!(help(&["nonsense"]), overview);
+
+        let issue_new = help(&["issue", "new"]);
+        assert!(issue_new.contains(
+            "Usage: gh issue new [--label <labels>...] [--assignee <assignees>...] \
+             <repo> <text>..."
+        ));
+        assert!(issue_new.contains("<repo>: Repository as `owner/name`, name or id"));
+        assert!(issue_new.contains("Examples:\ngh issue new github-bot"));
+        assert!(help(&["pr"]).contains("gh pr merge - Merge a PR"));
+        assert!(help(&["pr", "merge"]).contains("[--squash]"));
+        assert_eq!(
+            Cli::parse_from(["gh", "help", "issue", "new"]).command,
+            Commands::Help {
+                command: vec!["issue".to_string(), "new".to_string()]
+            }
+        );
+    }
+
+    /// Check that the examples attached to a command parse and run that command or one below it
+    fn check_examples<'a>(root: &Command, command: &'a Command, path: &[&'a str]) {
+        for example in own_examples(command) {
+            // placeholders like `<token>` stand for a single word
+            let words = example
+                .split(' ')
+                .map(|word| {
+                    if word.starts_with('<') && word.ends_with('>') {
+                        "ghp_xyz"
+                    } else {
+                        word
+                    }
+                })
+                .collect::<Vec<_>>();
+            if let Err(err) = Cli::try_parse_from(&words) {
+                panic!("example `{example}` doesn't parse: {err}");
+            }
+            assert!(
+                find(root, &words[1..]).1.starts_with(path),
+                "example `{example}` is attached to `{}`",
+                command_line(path, "")
+            );
+        }
+        for subcommand in command.get_subcommands() {
+            let mut path = path.to_vec();
+            path.push(subcommand.get_name());
+            check_examples(root, subcommand, &path);
+        }
+    }
+
+    #[test]
+    fn test_examples() {
+        let root = Cli::command();
+        check_examples(&root, &root, &[]);
+        for command in root.get_subcommands().filter(|c| !c.is_hide_set()) {
+            assert!(
+                !examples(&[command.get_name()]).is_empty(),
+                "`gh {}` has no example",
+                command.get_name()
+            );
+        }
+        assert!(help(&["issue"]).contains("gh issue new github-bot"));
+        assert!(help(&["issue", "close"]).contains("gh issue close github-bot 12"));
+        assert!(!help(&["pr", "close"]).contains("gh issue"));
+    }
+
+    #[test]
+    fn test_error_reply() {
+        assert!(reply("gh subscibe github-bot all")
+            .starts_with("Unknown command `gh subscibe`, did you mean `gh subscribe`?"));
+        assert!(reply("gh issue nwe github-bot title")
+            .starts_with("Unknown command `gh issue nwe`, did you mean `gh issue new`?"));
+        assert!(reply("gh xyz").starts_with("Unknown command `gh xyz`\n\nCommands:"));
+        assert!(reply("gh show").contains("Usage: gh show <reference>"));
+        assert_eq!(reply("gh pr --help"), help(&["pr"]));
+        assert!(reply("gh").starts_with("Commands:"));
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance("subscribe", "subscibe"), 1);
+        assert_eq!(distance("new", "nwe"), 2);
+        assert_eq!(distance("", "abc"), 3);
+    }
+}
@@ -1,10 +1,17 @@
 pub mod bot;
 pub mod db;
+pub mod filter;
+pub mod health;
+pub mod help;
+pub mod message;
 pub mod parser;
+pub mod personal;
+pub mod poller;
 pub mod rest_api;
 pub mod server;
 pub mod shared;
 pub mod utils;
+pub mod vault;
 
 use bot::Bot;
 use tokio::signal;
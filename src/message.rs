@@ -0,0 +1,491 @@
+//! Chat messages describing webhook events
+//!
+//! Messages are rendered from minijinja templates over the serialized events.
+//! Chats can replace the default template of an event type with their own.
+
+use anyhow::bail;
+use clap::ValueEnum;
+use itertools::Itertools;
+use minijinja::{Environment, Value};
+use strum_macros::Display;
+
+use crate::{
+    rest_api::{CheckRun, IssueItem, PullItem, Review},
+    shared::{
+        comment::CommentEvent,
+        issue::IssueEvent,
+        pr::{PREvent, PR},
+        push::PushEvent,
+        Label, WebhookEvent,
+    },
+};
+
+/// Number of characters of the body shown when an issue or PR is opened
+const BODY_EXCERPT_LENGTH: usize = 300;
+
+/// Instructions a template may execute for one message, so loops can't run forever
+const TEMPLATE_FUEL: u64 = 50_000;
+
+/// Longest template a chat can set
+const MAX_TEMPLATE_LENGTH: usize = 2_000;
+
+/// Longest message a template may render
+const MAX_MESSAGE_LENGTH: usize = 10_000;
+
+/// The types of events which have their own template
+#[derive(Copy, Clone, PartialEq, Eq, Debug, ValueEnum, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum EventKind {
+    Issue,
+    Pr,
+    Push,
+    Comment,
+}
+
+impl EventKind {
+    pub fn default_template(self) -> &'static str {
+        match self {
+            EventKind::Issue => include_str!("templates/issue.jinja"),
+            EventKind::Pr => include_str!("templates/pr.jinja"),
+            EventKind::Push => include_str!("templates/push.jinja"),
+            EventKind::Comment => include_str!("templates/comment.jinja"),
+        }
+    }
+
+    /// Example event taken from the fixtures, used to preview templates
+    pub fn example(self) -> WebhookEvent {
+        match self {
+            EventKind::Issue => WebhookEvent::Issue(
+                serde_json::from_str::<IssueEvent>(include_str!("../mock/issue_open.json"))
+                    .unwrap(),
+            ),
+            EventKind::Pr => WebhookEvent::PR(
+                serde_json::from_str::<PREvent>(include_str!("../mock/pr_closed.json")).unwrap(),
+            ),
+            EventKind::Push => WebhookEvent::Push(
+                serde_json::from_str::<PushEvent>(include_str!("../mock/push.json")).unwrap(),
+            ),
+            EventKind::Comment => WebhookEvent::Comment(
+                serde_json::from_str::<CommentEvent>(include_str!("../mock/comment_created.json"))
+                    .unwrap(),
+            ),
+        }
+    }
+}
+
+impl WebhookEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            WebhookEvent::Issue(_) => EventKind::Issue,
+            WebhookEvent::PR(_) => EventKind::Pr,
+            WebhookEvent::Push(_) => EventKind::Push,
+            WebhookEvent::Comment(_) => EventKind::Comment,
+        }
+    }
+}
+
+/// Render the notification about an event, using the default template if none is given
+pub fn render(event: &WebhookEvent, template: Option<&str>) -> anyhow::Result<String> {
+    let template = template.unwrap_or_else(|| event.kind().default_template());
+    render_context(template, context(event))
+}
+
+/// Like [render], but on the blocking thread pool so that slow templates don't stall the bot
+pub async fn render_detached(
+    event: &WebhookEvent,
+    template: Option<String>,
+) -> anyhow::Result<String> {
+    let template = template.unwrap_or_else(|| event.kind().default_template().to_string());
+    let context = context(event);
+    tokio::task::spawn_blocking(move || render_context(&template, context)).await?
+}
+
+/// Values a template of the event sees
+fn context(event: &WebhookEvent) -> Value {
+    match event {
+        WebhookEvent::Issue(event) => Value::from_serialize(event),
+        WebhookEvent::PR(event) => Value::from_serialize(event),
+        WebhookEvent::Push(event) => Value::from_serialize(event),
+        WebhookEvent::Comment(event) => Value::from_serialize(event),
+    }
+}
+
+fn render_context(template: &str, context: Value) -> anyhow::Result<String> {
+    if template.len() > MAX_TEMPLATE_LENGTH {
+        bail!("Templates can have at most {MAX_TEMPLATE_LENGTH} characters");
+    }
+    let text = environment().render_str(template, context)?;
+    if text.len() > MAX_MESSAGE_LENGTH {
+        bail!("The template renders messages longer than {MAX_MESSAGE_LENGTH} characters");
+    }
+    Ok(text)
+}
+
+/// What a PR adds to the summary of an issue
+pub struct PrStatus {
+    pub pr: PR,
+    pub reviews: Vec<Review>,
+    pub checks: Vec<CheckRun>,
+}
+
+/// Summary of an issue or PR, as shown by `gh show`
+pub fn summary(full_name: &str, item: &IssueItem, status: Option<&PrStatus>) -> String {
+    let state = match status {
+        Some(status) if status.pr.merged => "merged",
+        Some(status) if status.pr.draft && item.state == "open" => "draft",
+        _ => item.state.as_str(),
+    };
+    let mut text = match status {
+        Some(status) => format!(
+            "[{full_name}] PR #{} '{}' ({state}) by {} ({} → {})",
+            item.number, item.title, item.user.login, status.pr.head.name, status.pr.base.name
+        ),
+        None => format!(
+            "[{full_name}] #{} '{}' ({state}) by {}",
+            item.number, item.title, item.user.login
+        ),
+    };
+    if !item.labels.is_empty() {
+        let labels = item.labels.iter().map(|label| &label.name).join(", ");
+        text.push_str(&format!("\nLabels: {labels}"));
+    }
+    if !item.assignees.is_empty() {
+        let assignees = item.assignees.iter().map(|user| &user.login).join(", ");
+        text.push_str(&format!("\nAssignees: {assignees}"));
+    }
+    if let Some(status) = status {
+        text.push_str(&format!("\nReviews: {}", review_status(&status.reviews)));
+        text.push_str(&format!("\nCI: {}", check_status(&status.checks)));
+    }
+    if let Some(body) = item.body.as_deref().filter(|body| !body.trim().is_empty()) {
+        text.push_str(&format!("\n\n{}", excerpt(body.to_string())));
+    }
+    text.push_str(&format!("\n{}", item.html_url));
+    text
+}
+
+/// An issue in a list, like `#12 Crash on start [bug, ui]`
+pub fn issue_line(issue: &IssueItem) -> String {
+    format!(
+        "#{} {}{}",
+        issue.number,
+        issue.title,
+        label_list(&issue.labels)
+    )
+}
+
+/// A PR in a list, like `#3 Fix the layout by septias (draft) [ui]`
+pub fn pr_line(pr: &PullItem) -> String {
+    format!(
+        "#{} {} by {}{}{}",
+        pr.number,
+        pr.title,
+        pr.user.login,
+        if pr.draft { " (draft)" } else { "" },
+        label_list(&pr.labels)
+    )
+}
+
+fn label_list(labels: &[Label]) -> String {
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!(" [{}]", labels.iter().map(|label| &label.name).join(", "))
+    }
+}
+
+/// The latest verdict of every reviewer, like `approved by a; changes requested by b`
+fn review_status(reviews: &[Review]) -> String {
+    let mut verdicts: Vec<(&str, &str)> = vec![];
+    for review in reviews {
+        let login = review.user.login.as_str();
+        match review.state.as_str() {
+            "APPROVED" | "CHANGES_REQUESTED" => {
+                match verdicts.iter_mut().find(|(reviewer, _)| *reviewer == login) {
+                    Some(verdict) => verdict.1 = review.state.as_str(),
+                    None => verdicts.push((login, review.state.as_str())),
+                }
+            }
+            "DISMISSED" => verdicts.retain(|(reviewer, _)| *reviewer != login),
+            _ => {}
+        }
+    }
+    let by = |state: &str| {
+        verdicts
+            .iter()
+            .filter(|(_, verdict)| *verdict == state)
+            .map(|(reviewer, _)| reviewer)
+            .join(", ")
+    };
+    let parts = [
+        ("approved", by("APPROVED")),
+        ("changes requested", by("CHANGES_REQUESTED")),
+    ]
+    .into_iter()
+    .filter(|(_, reviewers)| !reviewers.is_empty())
+    .map(|(verdict, reviewers)| format!("{verdict} by {reviewers}"))
+    .join("; ");
+    if parts.is_empty() {
+        "none yet".to_string()
+    } else {
+        parts
+    }
+}
+
+/// Counts of passed, failed and pending check runs
+fn check_status(checks: &[CheckRun]) -> String {
+    if checks.is_empty() {
+        return "no checks".to_string();
+    }
+    let (mut passed, mut failed, mut pending) = (0, 0, 0);
+    for check in checks {
+        match check.conclusion.as_deref() {
+            _ if check.status != "completed" => pending += 1,
+            Some("success" | "neutral" | "skipped") => passed += 1,
+            _ => failed += 1,
+        }
+    }
+    [(passed, "passed"), (failed, "failed"), (pending, "pending")]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, state)| format!("{count} {state}"))
+        .join(", ")
+}
+
+/// Template environment with the filters available in templates
+fn environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_fuel(Some(TEMPLATE_FUEL));
+    env.add_filter("excerpt", excerpt);
+    env.add_filter("first_line", first_line);
+    env.add_filter("branch", branch);
+    env
+}
+
+/// Beginning of a longer text like the body of an issue
+fn excerpt(text: String) -> String {
+    let text = text.trim();
+    let mut excerpt = text.chars().take(BODY_EXCERPT_LENGTH).collect::<String>();
+    if excerpt.len() < text.len() {
+        excerpt.push('…');
+    }
+    excerpt
+}
+
+/// First line of a text like a commit message
+fn first_line(text: String) -> String {
+    text.lines().next().unwrap_or_default().to_string()
+}
+
+/// Branch name of a git ref like `refs/heads/main`
+fn branch(git_ref: String) -> String {
+    git_ref
+        .strip_prefix("refs/heads/")
+        .unwrap_or(&git_ref)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::User;
+
+    #[test]
+    fn test_issue_message() {
+        let mock = include_str!("../mock/issue_close.json");
+        let event = WebhookEvent::Issue(serde_json::from_str::<IssueEvent>(mock).unwrap());
+        assert_eq!(
+            render(&event, None).unwrap(),
+            "[Septias/testrepo] #1 'test' closed by Septias (completed)\n\
+             https://github.com/Septias/testrepo/issues/1"
+        );
+    }
+
+    #[test]
+    fn test_pr_message() {
+        assert_eq!(
+            render(&EventKind::Pr.example(), None).unwrap(),
+            "[Septias/testrepo] PR #3 'PR 2' merged by Septias into main (6ab8654)\n\
+             https://github.com/Septias/testrepo/pull/3"
+        );
+
+        let mock = include_str!("../mock/pr_opened.json");
+        let mut event = serde_json::from_str::<PREvent>(mock).unwrap();
+        event.pull_request.labels = vec![Label {
+            name: "bug".to_string(),
+        }];
+        event.pull_request.body = Some("Fixes the layout".to_string());
+        assert_eq!(
+            render(&WebhookEvent::PR(event), None).unwrap(),
+            "[Septias/testrepo] PR #2 'PR 2' opened by Septias (pr → main)\n\
+             Labels: bug\n\n\
+             Fixes the layout\n\
+             https://github.com/Septias/testrepo/pull/2"
+        );
+    }
+
+    #[test]
+    fn test_push_message() {
+        assert_eq!(
+            render(&EventKind::Push.example(), None).unwrap(),
+            "[Septias/testrepo] Septias pushed 2 commits to main\n\
+             0f6d5c3 Add landing page\n\
+             5a1f0b1 Fix typo\n\
+             https://github.com/Septias/testrepo/compare/d37b458e8a8d...5a1f0b1a7d7e"
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let mock: serde_json::Value =
+            serde_json::from_str(include_str!("../mock/pr_opened.json")).unwrap();
+        let pr = serde_json::from_value::<PR>(mock["pull_request"].clone()).unwrap();
+        let item = IssueItem {
+            number: pr.number,
+            title: pr.title.clone(),
+            html_url: pr.html_url.clone(),
+            body: Some("Fixes the layout".to_string()),
+            state: pr.state.clone(),
+            state_reason: None,
+            user: pr.user.clone(),
+            labels: vec![Label {
+                name: "bug".to_string(),
+            }],
+            assignees: vec![],
+            pull_request: Some(serde_json::json!({})),
+        };
+        let review = |login: &str, state: &str| Review {
+            user: User {
+                login: login.to_string(),
+            },
+            state: state.to_string(),
+        };
+        let check = |status: &str, conclusion: Option<&str>| CheckRun {
+            name: "ci".to_string(),
+            status: status.to_string(),
+            conclusion: conclusion.map(str::to_string),
+        };
+        let status = PrStatus {
+            pr,
+            reviews: vec![
+                review("hpk42", "CHANGES_REQUESTED"),
+                review("link2xt", "APPROVED"),
+                review("hpk42", "COMMENTED"),
+                review("r10s", "APPROVED"),
+                review("r10s", "DISMISSED"),
+            ],
+            checks: vec![
+                check("completed", Some("success")),
+                check("completed", Some("skipped")),
+                check("completed", Some("failure")),
+                check("in_progress", None),
+            ],
+        };
+        assert_eq!(
+            summary("Septias/testrepo", &item, Some(&status)),
+            "[Septias/testrepo] PR #2 'PR 2' (open) by Septias (pr → main)\n\
+             Labels: bug\n\
+             Reviews: approved by link2xt; changes requested by hpk42\n\
+             CI: 2 passed, 1 failed, 1 pending\n\n\
+             Fixes the layout\n\
+             https://github.com/Septias/testrepo/pull/2"
+        );
+        assert_eq!(review_status(&[]), "none yet");
+        assert_eq!(check_status(&[]), "no checks");
+    }
+
+    #[test]
+    fn test_issue_line() {
+        let user = User {
+            login: "Septias".to_string(),
+        };
+        let label = |name: &str| Label {
+            name: name.to_string(),
+        };
+        let mut issue = IssueItem {
+            number: 12,
+            title: "Crash on start".to_string(),
+            html_url: "https://github.com/Septias/testrepo/issues/12".to_string(),
+            body: None,
+            state: "open".to_string(),
+            state_reason: None,
+            user,
+            labels: vec![label("bug"), label("ui")],
+            assignees: vec![],
+            pull_request: None,
+        };
+        assert_eq!(issue_line(&issue), "#12 Crash on start [bug, ui]");
+        issue.labels.clear();
+        assert_eq!(issue_line(&issue), "#12 Crash on start");
+    }
+
+    #[test]
+    fn test_pr_line() {
+        let mut pr = PullItem {
+            number: 3,
+            title: "Fix the layout".to_string(),
+            html_url: "https://github.com/Septias/testrepo/pull/3".to_string(),
+            draft: true,
+            user: User {
+                login: "septias".to_string(),
+            },
+            labels: vec![Label {
+                name: "ui".to_string(),
+            }],
+            requested_reviewers: vec![],
+        };
+        assert_eq!(pr_line(&pr), "#3 Fix the layout by septias (draft) [ui]");
+        pr.draft = false;
+        pr.labels.clear();
+        assert_eq!(pr_line(&pr), "#3 Fix the layout by septias");
+    }
+
+    #[test]
+    fn test_comment_message() {
+        assert_eq!(
+            render(&EventKind::Comment.example(), None).unwrap(),
+            "[Septias/testrepo] Septias commented on #1 'test'\n\n\
+             @hpk42 can you take a look? cc @link2xt\n\
+             https://github.com/Septias/testrepo/issues/1#issuecomment-1302745012"
+        );
+    }
+
+    #[test]
+    fn test_custom_template() {
+        let template = "{{ sender.login }}: {{ issue.title | upper }}";
+        assert_eq!(
+            render(&EventKind::Issue.example(), Some(template)).unwrap(),
+            "Septias: TEST"
+        );
+        assert!(render(&EventKind::Issue.example(), Some("{{ issue.title")).is_err());
+        assert!(render(&EventKind::Push.example(), Some("{{ ref | nonsense }}")).is_err());
+    }
+
+    #[test]
+    fn test_template_limits() {
+        let event = EventKind::Issue.example();
+        let endless = "{% for a in range(9999) %}{% for b in range(9999) %}\
+                       {% endfor %}{% endfor %}";
+        assert!(render(&event, Some(endless)).is_err());
+
+        let long = "{% for a in range(3000) %}{{ issue.title }}{% endfor %}";
+        assert!(render(&event, Some(long)).is_err());
+
+        assert!(render(&event, Some(&"x".repeat(MAX_TEMPLATE_LENGTH + 1))).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_render_detached() {
+        let event = EventKind::Issue.example();
+        assert_eq!(
+            render_detached(&event, Some("{{ issue.title }}".to_string()))
+                .await
+                .unwrap(),
+            "test"
+        );
+        assert_eq!(
+            render_detached(&event, None).await.unwrap(),
+            render(&event, None).unwrap()
+        );
+    }
+}
@@ -1,47 +1,440 @@
 //! Parser for commands sent to the bot
 
-use clap::{arg, command, Parser, Subcommand};
+use clap::{arg, command, Parser, Subcommand, ValueEnum};
+use itertools::Itertools;
+use std::{fmt::Display, str::FromStr};
 
-use crate::shared::{issue::IssueAction, pr::PRAction};
+use crate::{
+    filter::Filter,
+    message::EventKind,
+    shared::{issue::IssueAction, pr::PRAction},
+};
 
 #[derive(Parser)]
-#[command(author = None, version = None, about = None, long_about = None)]
+#[command(
+    author = None,
+    version = None,
+    about = None,
+    long_about = None,
+    disable_help_subcommand = true
+)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
 }
 
+/// Commands of the bot
+///
+/// Examples for `gh help` are attached with `after_help`, one per line. The triage
+/// subcommands are shared by `issue` and `pr`, so their examples sit on those two.
 #[derive(Subcommand, PartialEq, Eq, Debug)]
 pub enum Commands {
+    /// Explain the commands, or one of them with examples
+    #[command(after_help = "gh help issue new")]
+    Help {
+        /// Command and subcommands to explain, e.g. `issue new`
+        command: Vec<String>,
+    },
+
     /// Subscribe to an event
+    #[command(after_help = "\
+        gh subscribe septias/github-bot issue opened,closed\n\
+        gh subscribe github-bot pr all --label bug --no-drafts\n\
+        gh subscribe github-bot push --base main --path web/**")]
     Subscribe {
-        /// Id of the repository
-        repo: usize,
+        /// Repository as `owner/name`, name or id
+        repo: String,
+        #[command(flatten)]
+        filter: Filter,
         #[command(subcommand)]
         family: Family,
     },
 
     /// Unsubscribe from an event
+    #[command(after_help = "gh unsubscribe github-bot all")]
     Unsubscribe {
-        /// Id of the repository
-        repo: usize,
+        /// Repository as `owner/name`, name or id, every repository if omitted. A repository
+        /// named `all`, `pr`, `issue` or `push` needs `owner/name` or its id here
+        repo: Option<String>,
         #[command(subcommand)]
         family: Family,
     },
 
+    /// List everything this chat is subscribed to
+    #[command(after_help = "gh subscriptions")]
+    Subscriptions,
+
     // Change supported repositories
     Repositories {
         #[command(subcommand)]
         repo_subcommands: RepoSubcommands,
     },
+
+    /// Check the webhooks of repositories
+    Health {
+        #[command(subcommand)]
+        health_subcommands: HealthSubcommands,
+    },
+
+    /// Change how messages about events look in this chat
+    Format {
+        #[command(subcommand)]
+        format_subcommands: FormatSubcommands,
+    },
+
+    /// Work with the issues of a repository
+    #[command(after_help = "\
+        gh issue close github-bot 12 --not-planned\n\
+        gh issue assign github-bot 12 septias")]
+    Issue {
+        #[command(subcommand)]
+        issue_subcommands: IssueSubcommands,
+    },
+
+    /// Work with the pull requests of a repository
+    #[command(after_help = "gh pr label github-bot 3 bug")]
+    Pr {
+        #[command(subcommand)]
+        pr_subcommands: PrSubcommands,
+    },
+
+    /// List the open issues of a repository
+    #[command(after_help = "gh issues github-bot --assignee me")]
+    Issues {
+        /// Repository as `owner/name`, name or id
+        repo: String,
+
+        /// Only issues with this label
+        #[arg(long = "label")]
+        labels: Vec<String>,
+
+        /// Only issues assigned to this user, `me` for yourself
+        #[arg(long)]
+        assignee: Option<String>,
+    },
+
+    /// List the open PRs of a repository
+    #[command(after_help = "gh prs github-bot --review-requested me")]
+    Prs {
+        /// Repository as `owner/name`, name or id
+        repo: String,
+
+        /// Only PRs waiting for a review of this user, `me` for yourself
+        #[arg(long)]
+        review_requested: Option<String>,
+    },
+
+    /// Show a summary of an issue or PR
+    #[command(after_help = "gh show septias/github-bot#12")]
+    Show {
+        /// The issue or PR as `repo#number`, e.g. `septias/github-bot#12`
+        reference: Reference,
+    },
+
+    /// Allow contacts to approve and merge PRs, only for the admin
+    Permissions {
+        #[command(subcommand)]
+        permission_subcommands: PermissionSubcommands,
+    },
+
+    /// Link yourself to your github login, by a public gist or by storing your token
+    #[command(after_help = "gh link septias")]
+    Link {
+        /// Your github login
+        login: String,
+    },
+
+    /// Remove the link to your github login
+    #[command(after_help = "gh unlink")]
+    Unlink,
+
+    /// Show which github login you are linked to
+    #[command(after_help = "gh whoami")]
+    Whoami,
+
+    /// Get review requests, assignments and mentions of your login in a 1:1 chat
+    Notifications {
+        #[command(subcommand)]
+        notification_subcommands: NotificationSubcommands,
+    },
+
+    /// Manage the github token used for what you do from chat, like commenting
+    Token {
+        #[command(subcommand)]
+        token_subcommands: TokenSubcommands,
+    },
+}
+
+#[derive(Subcommand, PartialEq, Eq, Debug)]
+pub enum IssueSubcommands {
+    /// Open an issue, the lines after the first one become its body
+    #[command(after_help = "gh issue new github-bot --label bug Crash on start")]
+    New {
+        /// Repository as `owner/name`, name or id
+        repo: String,
+
+        /// Label to add to the issue
+        #[arg(long = "label")]
+        labels: Vec<String>,
+
+        /// User to assign to the issue
+        #[arg(long = "assignee")]
+        assignees: Vec<String>,
+
+        /// Title of the issue, options have to come before it
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        text: Vec<String>,
+    },
+
+    #[command(flatten)]
+    Triage(TriageSubcommands),
+}
+
+#[derive(Subcommand, PartialEq, Eq, Debug)]
+pub enum PrSubcommands {
+    #[command(flatten)]
+    Triage(TriageSubcommands),
+
+    /// Approve a PR, needs a verified contact and permission from the admin
+    #[command(after_help = "gh pr approve github-bot 3 looks good")]
+    Approve {
+        /// Repository as `owner/name`, name or id
+        repo: String,
+        number: usize,
+
+        /// Comment to leave with the approval
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        comment: Vec<String>,
+    },
+
+    /// Merge a PR without conflicts or failed checks, needs a verified contact and permission
+    #[command(after_help = "gh pr merge github-bot 3 --squash")]
+    Merge {
+        /// Repository as `owner/name`, name or id
+        repo: String,
+        number: usize,
+
+        /// Squash the commits into one
+        #[arg(long, conflicts_with = "rebase")]
+        squash: bool,
+
+        /// Rebase the commits onto the base branch
+        #[arg(long)]
+        rebase: bool,
+    },
+}
+
+/// Small changes to an existing issue or PR, made with your own token
+#[derive(Subcommand, PartialEq, Eq, Debug)]
+pub enum TriageSubcommands {
+    /// Close it
+    Close {
+        /// Repository as `owner/name`, name or id
+        repo: String,
+        number: usize,
+
+        /// Close an issue as not planned instead of completed
+        #[arg(long)]
+        not_planned: bool,
+    },
+
+    /// Reopen it
+    Reopen {
+        /// Repository as `owner/name`, name or id
+        repo: String,
+        number: usize,
+    },
+
+    /// Add labels
+    Label {
+        /// Repository as `owner/name`, name or id
+        repo: String,
+        number: usize,
+        #[arg(required = true)]
+        labels: Vec<String>,
+    },
+
+    /// Remove labels
+    Unlabel {
+        /// Repository as `owner/name`, name or id
+        repo: String,
+        number: usize,
+        #[arg(required = true)]
+        labels: Vec<String>,
+    },
+
+    /// Assign users
+    Assign {
+        /// Repository as `owner/name`, name or id
+        repo: String,
+        number: usize,
+        #[arg(required = true)]
+        assignees: Vec<String>,
+    },
+}
+
+impl TriageSubcommands {
+    /// Repository and number of the issue or PR that is changed
+    pub fn target(&self) -> (&str, usize) {
+        match self {
+            TriageSubcommands::Close { repo, number, .. }
+            | TriageSubcommands::Reopen { repo, number }
+            | TriageSubcommands::Label { repo, number, .. }
+            | TriageSubcommands::Unlabel { repo, number, .. }
+            | TriageSubcommands::Assign { repo, number, .. } => (repo, *number),
+        }
+    }
+}
+
+/// Split the words after a command into the title on the first line and the body below
+pub fn title_and_body(words: &[String]) -> (String, String) {
+    let text = words.join(" ");
+    match text.split_once('\n') {
+        Some((title, body)) => (title.trim().to_string(), body.trim().to_string()),
+        None => (text.trim().to_string(), String::new()),
+    }
+}
+
+/// An issue or PR written as `repo#number` where the repo is `owner/name`, name or id
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub struct Reference {
+    pub repo: String,
+    pub number: usize,
+}
+
+impl FromStr for Reference {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.rsplit_once('#')
+            .filter(|(repo, _)| !repo.is_empty())
+            .and_then(|(repo, number)| {
+                Some(Self {
+                    repo: repo.to_string(),
+                    number: number.parse().ok()?,
+                })
+            })
+            .ok_or_else(|| format!("`{s}` is not of the form `repo#number`"))
+    }
+}
+
+impl Display for Reference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}#{}", self.repo, self.number)
+    }
+}
+
+/// Find references like `owner/name#12` in a chat message
+///
+/// Only the full form counts, so that a `#12` in a sentence isn't mistaken for one.
+pub fn references(text: &str) -> Vec<Reference> {
+    text.split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_ascii_alphanumeric()))
+        .filter_map(|word| word.parse::<Reference>().ok())
+        .filter(|reference| match reference.repo.split_once('/') {
+            Some((owner, name)) => [owner, name].iter().all(|part| {
+                !part.is_empty()
+                    && part
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+            }),
+            None => false,
+        })
+        .unique()
+        .collect()
+}
+
+#[derive(Subcommand, PartialEq, Eq, Debug)]
+pub enum NotificationSubcommands {
+    /// Start the personal notifications, needs a linked login
+    #[command(after_help = "gh notifications on")]
+    On,
+
+    /// Stop them
+    Off,
+}
+
+#[derive(Subcommand, PartialEq, Eq, Debug)]
+pub enum PermissionSubcommands {
+    /// Allow a contact to approve and merge PRs of a repository
+    #[command(after_help = "gh permissions grant github-bot alice@example.org")]
+    Grant {
+        /// Repository as `owner/name`, name or id
+        repo: String,
+
+        /// Email address of the contact
+        addr: String,
+    },
+
+    /// Take the permission away again
+    Revoke {
+        /// Repository as `owner/name`, name or id
+        repo: String,
+
+        /// Email address of the contact
+        addr: String,
+    },
+}
+
+#[derive(Subcommand, PartialEq, Eq, Debug)]
+pub enum TokenSubcommands {
+    /// Store your personal access token, only accepted in a 1:1 chat with the bot
+    #[command(after_help = "gh token set <token>")]
+    Set { token: String },
+
+    /// Forget your token
+    Remove,
+}
+
+#[derive(Subcommand, PartialEq, Eq, Debug)]
+pub enum FormatSubcommands {
+    /// Use a minijinja template for messages about an event type
+    #[command(
+        after_help = "gh format set pr {{ pull_request.title }} {{ action }} by {{ sender.login }}"
+    )]
+    Set {
+        event: EventKind,
+
+        /// Template rendered with the fields of the webhook payload
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        template: Vec<String>,
+    },
+
+    /// Go back to the default template of an event type, or of all if omitted
+    Reset { event: Option<EventKind> },
+
+    /// Show a message about an example event
+    #[command(after_help = "gh format preview pr")]
+    Preview { event: EventKind },
+}
+
+#[derive(Subcommand, PartialEq, Eq, Debug)]
+pub enum HealthSubcommands {
+    /// Send alerts about failing webhooks to this chat instead of the 1:1 chat, only for the admin
+    Admin,
+
+    /// Check the webhook of a repository now
+    #[command(after_help = "gh health check github-bot")]
+    Check {
+        /// Repository as `owner/name`, name or id
+        repository: String,
+    },
+
+    /// Ask github to resend all failed deliveries of a repository
+    Redeliver {
+        /// Repository as `owner/name`, name or id
+        repository: String,
+    },
 }
 
 #[derive(Subcommand, PartialEq, Eq, Debug)]
 pub enum RepoSubcommands {
     // List all available repositories
+    #[command(after_help = "gh repositories list")]
     List,
 
     // Add a webhook for a new repository
+    #[command(after_help = "gh repositories add septias github-bot <api_key>")]
     Add {
         // Name of repo owner (user or organisation)
         owner: String,
@@ -52,12 +445,16 @@ pub enum RepoSubcommands {
         // REST-Api key
         // help: https://docs.github.com/en/authentication/managing-commit-signature-verification/adding-a-gpg-key-to-your-github-account
         api_key: String,
+
+        /// Poll the events instead of registering a webhook
+        #[arg(long)]
+        polling: bool,
     },
 
     // Remove a repositories webhook
     Remove {
-        // Id of repository to remove
-        repository: usize,
+        // Repository to remove as `owner/name`, name or id
+        repository: String,
 
         // REST-Api key
         // help: https://docs.github.com/en/authentication/managing-commit-signature-verification/adding-a-gpg-key-to-your-github-account
@@ -65,16 +462,79 @@ pub enum RepoSubcommands {
     },
 }
 
-#[derive(Subcommand, PartialEq, Eq, Debug)]
+#[derive(Subcommand, PartialEq, Eq, Debug, Clone)]
 pub enum Family {
     Pr {
-        #[arg(value_enum)]
-        pr_action: PRAction,
+        /// Comma separated actions or `all`
+        #[arg(value_delimiter = ',', required = true)]
+        pr_actions: Vec<ActionArg<PRAction>>,
     },
     Issue {
-        #[arg(value_enum)]
-        issue_action: IssueAction,
+        /// Comma separated actions or `all`
+        #[arg(value_delimiter = ',', required = true)]
+        issue_actions: Vec<ActionArg<IssueAction>>,
     },
+    /// Pushes to the repository
+    Push,
+    /// All events of the repository
+    All,
+}
+
+impl Family {
+    /// Names of the subscriber lists the selected actions are stored in
+    ///
+    /// Wildcards are stored in their own list so they also cover actions added later.
+    pub fn lists(&self) -> Vec<String> {
+        match self {
+            Family::Pr { pr_actions } => action_lists("pr", pr_actions),
+            Family::Issue { issue_actions } => action_lists("issue", issue_actions),
+            // pushes have no actions, so the wildcard is their only list
+            Family::Push => vec!["push_all".to_string()],
+            Family::All => vec!["all".to_string()],
+        }
+    }
+}
+
+fn action_lists<A: Display>(family: &str, actions: &[ActionArg<A>]) -> Vec<String> {
+    if actions
+        .iter()
+        .any(|action| matches!(action, ActionArg::All))
+    {
+        return vec![format!("{family}_all")];
+    }
+    actions
+        .iter()
+        .map(|action| format!("{family}_{action}"))
+        .unique()
+        .collect()
+}
+
+/// An action given on the command line, either a specific one or `all`
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum ActionArg<A> {
+    All,
+    Action(A),
+}
+
+impl<A: ValueEnum> FromStr for ActionArg<A> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("all") {
+            Ok(Self::All)
+        } else {
+            A::from_str(s, true).map(Self::Action)
+        }
+    }
+}
+
+impl<A: Display> Display for ActionArg<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ActionArg::All => write!(f, "all"),
+            ActionArg::Action(action) => action.fmt(f),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -87,11 +547,315 @@ mod tests {
         assert_eq!(
             cli.command,
             Commands::Subscribe {
-                repo: 558781383,
+                repo: "558781383".to_string(),
+                filter: Filter::default(),
                 family: Family::Issue {
-                    issue_action: IssueAction::Opened
+                    issue_actions: vec![ActionArg::Action(IssueAction::Opened)]
+                }
+            }
+        )
+    }
+
+    #[test]
+    fn test_several_actions() {
+        let cli = Cli::parse_from("wat subscribe 12 issue opened,closed,reopened".split(' '));
+        let Commands::Subscribe { family, .. } = cli.command else {
+            panic!("not a subscription")
+        };
+        assert_eq!(
+            family.lists(),
+            ["issue_opened", "issue_closed", "issue_reopened"]
+        );
+
+        let cli = Cli::parse_from("wat subscribe 12 pr merged,closed".split(' '));
+        let Commands::Subscribe { family, .. } = cli.command else {
+            panic!("not a subscription")
+        };
+        assert_eq!(family.lists(), ["pr_merged", "pr_closed"]);
+
+        let cli = Cli::parse_from("wat subscribe 12 pr opened,all".split(' '));
+        let Commands::Subscribe { family, .. } = cli.command else {
+            panic!("not a subscription")
+        };
+        assert_eq!(family.lists(), ["pr_all"]);
+
+        let cli = Cli::parse_from("wat subscribe 12 all".split(' '));
+        let Commands::Subscribe { family, .. } = cli.command else {
+            panic!("not a subscription")
+        };
+        assert_eq!(family.lists(), ["all"]);
+
+        let cli = Cli::parse_from("wat subscribe 12 pr review_requested,synchronize".split(' '));
+        let Commands::Subscribe { family, .. } = cli.command else {
+            panic!("not a subscription")
+        };
+        assert_eq!(family.lists(), ["pr_review_requested", "pr_synchronize"]);
+
+        assert!(Cli::try_parse_from("wat subscribe 12 pr opened,nonsense".split(' ')).is_err());
+    }
+
+    #[test]
+    fn test_unsubscribe_all() {
+        let cli = Cli::parse_from("wat unsubscribe all".split(' '));
+        assert_eq!(
+            cli.command,
+            Commands::Unsubscribe {
+                repo: None,
+                family: Family::All
+            }
+        );
+        let cli = Cli::parse_from("wat unsubscribe github-bot all".split(' '));
+        assert_eq!(
+            cli.command,
+            Commands::Unsubscribe {
+                repo: Some("github-bot".to_string()),
+                family: Family::All
+            }
+        );
+        // a repository named like a family is read as the family unless written as `owner/name`
+        assert!(Cli::try_parse_from("wat unsubscribe all all".split(' ')).is_err());
+        let cli = Cli::parse_from("wat unsubscribe septias/all all".split(' '));
+        assert_eq!(
+            cli.command,
+            Commands::Unsubscribe {
+                repo: Some("septias/all".to_string()),
+                family: Family::All
+            }
+        )
+    }
+
+    #[test]
+    fn test_listen_by_name() {
+        let cli = Cli::parse_from("wat subscribe septias/github-bot pr opened".split(' '));
+        assert_eq!(
+            cli.command,
+            Commands::Subscribe {
+                repo: "septias/github-bot".to_string(),
+                filter: Filter::default(),
+                family: Family::Pr {
+                    pr_actions: vec![ActionArg::Action(PRAction::Opened)]
                 }
             }
         )
     }
+
+    #[test]
+    fn test_format() {
+        let cli = Cli::parse_from(
+            "wat format set pr {{ sender.login }} -> {{ pull_request.title }}".split(' '),
+        );
+        let Commands::Format {
+            format_subcommands: FormatSubcommands::Set { event, template },
+        } = cli.command
+        else {
+            panic!("not a template")
+        };
+        assert_eq!(event, EventKind::Pr);
+        assert_eq!(
+            template.join(" "),
+            "{{ sender.login }} -> {{ pull_request.title }}"
+        );
+
+        let cli = Cli::parse_from("wat format reset".split(' '));
+        assert_eq!(
+            cli.command,
+            Commands::Format {
+                format_subcommands: FormatSubcommands::Reset { event: None }
+            }
+        );
+    }
+
+    #[test]
+    fn test_issue_new() {
+        let cli = Cli::parse_from(
+            "wat issue new github-bot --label bug --label ui Crash on start\nSteps:\n1. start it"
+                .split(' '),
+        );
+        let Commands::Issue {
+            issue_subcommands:
+                IssueSubcommands::New {
+                    repo,
+                    labels,
+                    assignees,
+                    text,
+                },
+        } = cli.command
+        else {
+            panic!("not a new issue")
+        };
+        assert_eq!(repo, "github-bot");
+        assert_eq!(labels, ["bug", "ui"]);
+        assert!(assignees.is_empty());
+        assert_eq!(
+            title_and_body(&text),
+            (
+                "Crash on start".to_string(),
+                "Steps:\n1. start it".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_triage() {
+        let cli = Cli::parse_from("wat issue close github-bot 12 --not-planned".split(' '));
+        assert_eq!(
+            cli.command,
+            Commands::Issue {
+                issue_subcommands: IssueSubcommands::Triage(TriageSubcommands::Close {
+                    repo: "github-bot".to_string(),
+                    number: 12,
+                    not_planned: true
+                })
+            }
+        );
+
+        let cli = Cli::parse_from("wat pr label septias/github-bot 3 bug ui".split(' '));
+        let Commands::Pr {
+            pr_subcommands: PrSubcommands::Triage(triage),
+        } = cli.command
+        else {
+            panic!("not a pr command")
+        };
+        assert_eq!(triage.target(), ("septias/github-bot", 3));
+        assert!(
+            matches!(triage, TriageSubcommands::Label { labels, .. } if labels == ["bug", "ui"])
+        );
+
+        assert!(Cli::try_parse_from("wat pr assign github-bot 3".split(' ')).is_err());
+
+        let cli = Cli::parse_from("wat pr approve github-bot 3 looks good".split(' '));
+        assert_eq!(
+            cli.command,
+            Commands::Pr {
+                pr_subcommands: PrSubcommands::Approve {
+                    repo: "github-bot".to_string(),
+                    number: 3,
+                    comment: vec!["looks".to_string(), "good".to_string()]
+                }
+            }
+        );
+        let cli = Cli::parse_from("wat pr merge github-bot 3 --squash".split(' '));
+        assert!(matches!(
+            cli.command,
+            Commands::Pr {
+                pr_subcommands: PrSubcommands::Merge {
+                    squash: true,
+                    rebase: false,
+                    ..
+                }
+            }
+        ));
+        assert!(
+            Cli::try_parse_from("wat pr merge github-bot 3 --squash --rebase".split(' ')).is_err()
+        );
+        assert!(Cli::try_parse_from("wat issue reopen github-bot three".split(' ')).is_err());
+    }
+
+    #[test]
+    fn test_references() {
+        let cli = Cli::parse_from("wat show septias/github-bot#12".split(' '));
+        assert_eq!(
+            cli.command,
+            Commands::Show {
+                reference: Reference {
+                    repo: "septias/github-bot".to_string(),
+                    number: 12
+                }
+            }
+        );
+        assert!(Cli::try_parse_from("wat show github-bot".split(' ')).is_err());
+        assert!(Cli::try_parse_from("wat show #12".split(' ')).is_err());
+
+        assert_eq!(
+            references("see septias/github-bot#12, (and Septias/testrepo#3) not #4 or repo#5"),
+            [
+                Reference {
+                    repo: "septias/github-bot".to_string(),
+                    number: 12
+                },
+                Reference {
+                    repo: "Septias/testrepo".to_string(),
+                    number: 3
+                }
+            ]
+        );
+        assert!(references("a/b/c#1 https://github.com/a/b#1").is_empty());
+    }
+
+    #[test]
+    fn test_lists() {
+        let cli = Cli::parse_from("wat issues github-bot --label bug --assignee me".split(' '));
+        assert_eq!(
+            cli.command,
+            Commands::Issues {
+                repo: "github-bot".to_string(),
+                labels: vec!["bug".to_string()],
+                assignee: Some("me".to_string())
+            }
+        );
+        let cli = Cli::parse_from("wat prs github-bot --review-requested septias".split(' '));
+        assert_eq!(
+            cli.command,
+            Commands::Prs {
+                repo: "github-bot".to_string(),
+                review_requested: Some("septias".to_string())
+            }
+        );
+    }
+
+    #[test]
+    fn test_link() {
+        let cli = Cli::parse_from("wat link Septias".split(' '));
+        assert_eq!(
+            cli.command,
+            Commands::Link {
+                login: "Septias".to_string()
+            }
+        );
+        assert_eq!(Cli::parse_from(["wat", "whoami"]).command, Commands::Whoami);
+        assert!(Cli::try_parse_from(["wat", "link"]).is_err());
+        assert_eq!(
+            Cli::parse_from(["wat", "notifications", "on"]).command,
+            Commands::Notifications {
+                notification_subcommands: NotificationSubcommands::On
+            }
+        );
+    }
+
+    #[test]
+    fn test_filter() {
+        let cli = Cli::parse_from(
+            "wat subscribe 12 pr opened --label bug --author !dependabot[bot] --label ui"
+                .split(' '),
+        );
+        assert_eq!(
+            cli.command,
+            Commands::Subscribe {
+                repo: "12".to_string(),
+                filter: Filter {
+                    labels: vec!["bug".to_string(), "ui".to_string()],
+                    authors: vec!["!dependabot[bot]".to_string()],
+                    ..Default::default()
+                },
+                family: Family::Pr {
+                    pr_actions: vec![ActionArg::Action(PRAction::Opened)]
+                }
+            }
+        );
+
+        let cli = Cli::parse_from("wat subscribe 12 --base main pr all --no-drafts".split(' '));
+        let Commands::Subscribe { filter, .. } = cli.command else {
+            panic!("not a subscription")
+        };
+        assert_eq!(filter.bases, ["main"]);
+        assert!(filter.no_drafts);
+
+        let cli = Cli::parse_from("wat subscribe 12 push --path web/** --path !**/*.md".split(' '));
+        let Commands::Subscribe { filter, family, .. } = cli.command else {
+            panic!("not a subscription")
+        };
+        assert_eq!(filter.paths, ["web/**", "!**/*.md"]);
+        assert_eq!(family.lists(), ["push_all"]);
+        assert!(Cli::try_parse_from("wat subscribe 12 push --path web/[".split(' ')).is_err());
+    }
 }
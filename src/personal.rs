@@ -0,0 +1,179 @@
+//! Personal notifications for contacts linked to a github login
+//!
+//! Linked contacts can ask to be told directly when they are requested as reviewer,
+//! assigned or mentioned, without subscribing to whole repositories.
+
+use itertools::Itertools;
+use std::fmt::Display;
+
+use crate::shared::{comment::CommentAction, issue::IssueAction, pr::PRAction, User, WebhookEvent};
+
+/// Webhook events the personal notifications are taken from
+pub const PERSONAL_EVENTS: [&str; 3] = ["issue_comment", "issues", "pull_request"];
+
+/// Why a user is notified personally
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Reason {
+    ReviewRequested,
+    Assigned,
+    Mentioned,
+}
+
+impl Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reason::ReviewRequested => write!(f, "Your review was requested:"),
+            Reason::Assigned => write!(f, "You were assigned:"),
+            Reason::Mentioned => write!(f, "You were mentioned:"),
+        }
+    }
+}
+
+/// The logins an event concerns personally, each with the first reason that applies
+///
+/// Nobody is notified about what they did themselves. Mentions in private repositories
+/// are left out, since anyone can be mentioned, even those who can't see the repository.
+pub fn recipients(event: &WebhookEvent) -> Vec<(String, Reason)> {
+    let user = |user: &Option<User>, reason| {
+        user.iter()
+            .map(|user| (user.login.to_lowercase(), reason))
+            .collect::<Vec<_>>()
+    };
+    let private = event.repository().private;
+    let mentioned = |body: Option<&str>| {
+        if private {
+            return vec![];
+        }
+        mentions(body.unwrap_or_default())
+            .into_iter()
+            .map(|login| (login, Reason::Mentioned))
+            .collect::<Vec<_>>()
+    };
+    let (sender, recipients) = match event {
+        WebhookEvent::Issue(event) => (
+            &event.sender,
+            match event.action {
+                IssueAction::Assigned => user(&event.assignee, Reason::Assigned),
+                IssueAction::Opened => mentioned(event.issue.body.as_deref()),
+                _ => vec![],
+            },
+        ),
+        WebhookEvent::PR(event) => (
+            &event.sender,
+            match event.action {
+                PRAction::ReviewRequested => {
+                    user(&event.requested_reviewer, Reason::ReviewRequested)
+                }
+                PRAction::Assigned => user(&event.assignee, Reason::Assigned),
+                PRAction::Opened => mentioned(event.pull_request.body.as_deref()),
+                _ => vec![],
+            },
+        ),
+        WebhookEvent::Comment(event) => (
+            &event.sender,
+            match event.action {
+                CommentAction::Created => mentioned(Some(event.comment.body.as_str())),
+                _ => vec![],
+            },
+        ),
+        WebhookEvent::Push(_) => return vec![],
+    };
+    recipients
+        .into_iter()
+        .filter(|(login, _)| !login.eq_ignore_ascii_case(&sender.login))
+        .unique_by(|(login, _)| login.clone())
+        .collect()
+}
+
+/// Lowercase logins mentioned like `@login` in a text, leaving out email addresses
+fn mentions(text: &str) -> Vec<String> {
+    let is_login_char = |c: char| c.is_ascii_alphanumeric() || c == '-';
+    text.char_indices()
+        .filter(|(i, c)| {
+            *c == '@'
+                && !text[..*i]
+                    .chars()
+                    .next_back()
+                    .map_or(false, |c| is_login_char(c) || c == '.' || c == '_')
+        })
+        .map(|(i, _)| {
+            text[i + 1..]
+                .chars()
+                .take_while(|c| is_login_char(*c))
+                .collect::<String>()
+        })
+        .filter(|login| !login.is_empty() && !login.starts_with('-'))
+        .map(|login| login.trim_end_matches('-').to_lowercase())
+        .unique()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        message::EventKind,
+        shared::{issue::IssueEvent, pr::PREvent},
+    };
+
+    #[test]
+    fn test_mentions() {
+        assert_eq!(
+            mentions("@hpk42 can you look at this? cc @Link2xt, @hpk42.\nmail me@example.org"),
+            ["hpk42", "link2xt"]
+        );
+        assert!(mentions("no one @ all, not even @-dash").is_empty());
+    }
+
+    #[test]
+    fn test_recipients() {
+        assert_eq!(
+            recipients(&EventKind::Comment.example()),
+            [
+                ("hpk42".to_string(), Reason::Mentioned),
+                ("link2xt".to_string(), Reason::Mentioned)
+            ]
+        );
+
+        let mock = include_str!("../mock/pr_opened.json");
+        let mut event = serde_json::from_str::<PREvent>(mock).unwrap();
+        event.action = PRAction::ReviewRequested;
+        event.requested_reviewer = Some(User {
+            login: "hpk42".to_string(),
+        });
+        assert_eq!(
+            recipients(&WebhookEvent::PR(event)),
+            [("hpk42".to_string(), Reason::ReviewRequested)]
+        );
+
+        // assigning yourself isn't worth a notification
+        let mock = include_str!("../mock/issue_open.json");
+        let mut event = serde_json::from_str::<IssueEvent>(mock).unwrap();
+        event.action = IssueAction::Assigned;
+        event.assignee = Some(event.sender.clone());
+        assert!(recipients(&WebhookEvent::Issue(event)).is_empty());
+        assert!(recipients(&EventKind::Push.example()).is_empty());
+    }
+
+    #[test]
+    fn test_private_mentions() {
+        let WebhookEvent::Comment(mut event) = EventKind::Comment.example() else {
+            unreachable!()
+        };
+        event.repository.private = true;
+        assert!(recipients(&WebhookEvent::Comment(event)).is_empty());
+
+        // review requests imply access to the repository
+        let mock = include_str!("../mock/pr_opened.json");
+        let mut event = serde_json::from_str::<PREvent>(mock).unwrap();
+        event.repository.private = true;
+        event.action = PRAction::ReviewRequested;
+        event.requested_reviewer = Some(User {
+            login: "hpk42".to_string(),
+        });
+        assert_eq!(
+            recipients(&WebhookEvent::PR(event)),
+            [("hpk42".to_string(), Reason::ReviewRequested)]
+        );
+    }
+}
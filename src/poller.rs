@@ -0,0 +1,367 @@
+//! Polling of repository events for bots that can't receive webhooks
+
+use anyhow::Context as _;
+use log::{debug, error};
+use serde::Deserialize;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc::Sender;
+
+use crate::{
+    bot::State,
+    db::PollCursor,
+    rest_api::{get_pr, get_repo_events, is_private, list_updated_issues, RepoEvent},
+    shared::{
+        comment::{Comment, CommentAction, CommentEvent},
+        issue::{Issue, IssueAction, IssueEvent},
+        pr::{PRAction, PREvent, PR},
+        push::{Commit, PushEvent},
+        Repository, User, WebhookEvent,
+    },
+};
+
+/// Time between two polls of all polled repositories
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct Poller {
+    channel: Sender<WebhookEvent>,
+}
+
+impl Poller {
+    pub fn new(channel: Sender<WebhookEvent>) -> Self {
+        Self { channel }
+    }
+
+    /// Periodically poll all repositories in polling mode and
+    /// send their events to the same channel the webhook server uses
+    pub fn start(&self, state: Arc<State>) -> tokio::task::JoinHandle<()> {
+        let channel = self.channel.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                for repo in state.db.get_repository_ids().await.unwrap_or_default() {
+                    match poll(&state, repo).await {
+                        Ok(events) => {
+                            for event in events {
+                                channel.send(event).await.unwrap();
+                            }
+                        }
+                        Err(err) => error!("failed to poll repository {repo}: {err}"),
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Fetch the events of a repository that happened since the last poll
+async fn poll(state: &State, repo: usize) -> anyhow::Result<Vec<WebhookEvent>> {
+    if !state.db.is_polling(repo).await? {
+        return Ok(vec![]);
+    }
+    let api_key = state
+        .db
+        .get_api_key(repo)
+        .await?
+        .context("no api key stored for this repository")?;
+    let owner = state.db.get_owner(repo).await?;
+    let name = state.db.get_name(repo).await?;
+    let cursor = state.db.get_poll_cursor(repo).await?;
+
+    let Some(pages) = get_repo_events(
+        &owner,
+        &name,
+        &api_key,
+        cursor.etag.as_deref(),
+        cursor.since,
+    )
+    .await?
+    else {
+        return Ok(vec![]);
+    };
+    let newest = pages
+        .items
+        .iter()
+        .filter_map(|event| event.id.parse::<u64>().ok())
+        .max();
+    let newest_time = pages.items.first().map(|event| event.created_at.clone());
+
+    // the first poll only sets the cursor so that old events aren't replayed
+    let events = match cursor.since {
+        Some(_) => {
+            let repository = Repository {
+                id: repo,
+                full_name: format!("{owner}/{name}"),
+                html_url: format!("https://github.com/{owner}/{name}"),
+                name: name.clone(),
+                url: state.db.get_url(repo).await?,
+                // set from the visibility of each event
+                private: false,
+            };
+            let mut events = vec![];
+            // the timeline only keeps the last 300 events, so older ones can be lost
+            if let (false, Some(updated)) = (pages.reached, &cursor.updated) {
+                events =
+                    missed_openings(&owner, &name, &api_key, updated, &pages.items, &repository)
+                        .await?;
+            }
+            events.extend(
+                pages
+                    .items
+                    .into_iter()
+                    .rev()
+                    .filter_map(|event| to_webhook_event(event, &repository)),
+            );
+            events
+        }
+        None => vec![],
+    };
+
+    state
+        .db
+        .set_poll_cursor(
+            repo,
+            &PollCursor {
+                etag: pages.etag,
+                since: newest.or(cursor.since),
+                updated: newest_time.or(cursor.updated),
+            },
+        )
+        .await?;
+    Ok(events)
+}
+
+/// Events for the issues and pull requests opened since `since` that the timeline lost
+async fn missed_openings(
+    owner: &str,
+    name: &str,
+    key: &str,
+    since: &str,
+    timeline: &[RepoEvent],
+    repository: &Repository,
+) -> anyhow::Result<Vec<WebhookEvent>> {
+    let in_timeline = timeline
+        .iter()
+        .filter(|event| event.payload["action"] == "opened")
+        .filter_map(|event| {
+            event
+                .payload
+                .pointer("/issue/number")
+                .or_else(|| event.payload.pointer("/pull_request/number"))
+                .and_then(serde_json::Value::as_u64)
+        })
+        .collect::<Vec<_>>();
+
+    let repository = &Repository {
+        private: is_private(owner, name, key).await?,
+        ..repository.clone()
+    };
+    let mut missed = list_updated_issues(owner, name, key, since)
+        .await?
+        .into_iter()
+        .filter(|item| item.created_at.as_str() > since)
+        .filter(|item| !in_timeline.contains(&(item.issue.number as u64)))
+        .collect::<Vec<_>>();
+    missed.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+
+    let mut events = vec![];
+    for item in missed {
+        if item.pull_request.is_some() {
+            let pull_request = get_pr(owner, name, item.issue.number, key).await?;
+            events.push(WebhookEvent::PR(PREvent {
+                action: PRAction::Opened,
+                sender: pull_request.user.clone(),
+                repository: repository.clone(),
+                pull_request,
+                assignee: None,
+                requested_reviewer: None,
+            }));
+        } else {
+            events.push(WebhookEvent::Issue(IssueEvent {
+                action: IssueAction::Opened,
+                sender: item.issue.user.clone(),
+                repository: repository.clone(),
+                issue: item.issue,
+                assignee: None,
+            }));
+        }
+    }
+    Ok(events)
+}
+
+#[derive(Deserialize)]
+struct IssuePayload {
+    action: IssueAction,
+    issue: Issue,
+    #[serde(default)]
+    assignee: Option<User>,
+}
+
+#[derive(Deserialize)]
+struct CommentPayload {
+    action: CommentAction,
+    issue: Issue,
+    comment: Comment,
+}
+
+#[derive(Deserialize)]
+struct PRPayload {
+    action: PRAction,
+    pull_request: PR,
+    #[serde(default)]
+    assignee: Option<User>,
+    #[serde(default)]
+    requested_reviewer: Option<User>,
+}
+
+#[derive(Deserialize)]
+struct PushPayload {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    head: String,
+    before: String,
+    #[serde(default)]
+    commits: Vec<PushCommit>,
+}
+
+#[derive(Deserialize)]
+struct PushCommit {
+    sha: String,
+    message: String,
+}
+
+/// Shortened sha as used by github in compare urls
+fn short_sha(sha: &str) -> &str {
+    sha.get(..12).unwrap_or(sha)
+}
+
+/// Convert an entry of the event timeline to the event a webhook would have sent
+fn to_webhook_event(event: RepoEvent, repository: &Repository) -> Option<WebhookEvent> {
+    let repository = &Repository {
+        private: !event.public,
+        ..repository.clone()
+    };
+    let converted = match event.kind.as_str() {
+        "IssuesEvent" => serde_json::from_value::<IssuePayload>(event.payload).map(|payload| {
+            WebhookEvent::Issue(IssueEvent {
+                action: payload.action,
+                sender: event.actor,
+                repository: repository.clone(),
+                issue: payload.issue,
+                assignee: payload.assignee,
+            })
+        }),
+        "PullRequestEvent" => serde_json::from_value::<PRPayload>(event.payload).map(|payload| {
+            WebhookEvent::PR(PREvent {
+                action: payload.action.resolve(&payload.pull_request),
+                sender: event.actor,
+                repository: repository.clone(),
+                pull_request: payload.pull_request,
+                assignee: payload.assignee,
+                requested_reviewer: payload.requested_reviewer,
+            })
+        }),
+        "IssueCommentEvent" => {
+            serde_json::from_value::<CommentPayload>(event.payload).map(|payload| {
+                WebhookEvent::Comment(CommentEvent {
+                    action: payload.action,
+                    sender: event.actor,
+                    repository: repository.clone(),
+                    issue: payload.issue,
+                    comment: payload.comment,
+                })
+            })
+        }
+        // the timeline doesn't list the files of the commits
+        "PushEvent" => serde_json::from_value::<PushPayload>(event.payload).map(|payload| {
+            WebhookEvent::Push(PushEvent {
+                git_ref: payload.git_ref,
+                sender: event.actor,
+                compare: format!(
+                    "{}/compare/{}...{}",
+                    repository.html_url,
+                    short_sha(&payload.before),
+                    short_sha(&payload.head)
+                ),
+                repository: repository.clone(),
+                commits: payload
+                    .commits
+                    .into_iter()
+                    .map(|commit| Commit {
+                        id: commit.sha,
+                        message: commit.message,
+                        added: vec![],
+                        removed: vec![],
+                        modified: vec![],
+                    })
+                    .collect(),
+            })
+        }),
+        _ => return None,
+    };
+    converted
+        .map_err(|err| debug!("skipping event {}: {err}", event.id))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repository() -> Repository {
+        Repository {
+            id: 558781383,
+            name: "testrepo".to_owned(),
+            full_name: "Septias/testrepo".to_owned(),
+            url: "https://api.github.com/repos/Septias/testrepo".to_string(),
+            html_url: "https://github.com/Septias/testrepo".to_string(),
+            private: false,
+        }
+    }
+
+    #[test]
+    fn test_to_webhook_event() {
+        let mock = include_str!("../mock/repo_events.json");
+        let repository = repository();
+        let events = serde_json::from_str::<Vec<RepoEvent>>(mock)
+            .unwrap()
+            .into_iter()
+            .filter_map(|event| to_webhook_event(event, &repository))
+            .collect::<Vec<_>>();
+
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            &events[0],
+            WebhookEvent::PR(PREvent {
+                action: PRAction::Opened,
+                ..
+            })
+        ));
+        assert!(matches!(
+            &events[1],
+            WebhookEvent::Issue(IssueEvent {
+                action: IssueAction::Opened,
+                sender,
+                ..
+            }) if sender.login == "Septias"
+        ));
+    }
+
+    #[test]
+    fn test_push_event() {
+        let mock = include_str!("../mock/repo_push_event.json");
+        let event = serde_json::from_str::<RepoEvent>(mock).unwrap();
+        let Some(WebhookEvent::Push(push)) = to_webhook_event(event, &repository()) else {
+            panic!("push event wasn't converted");
+        };
+        assert_eq!(push.branch(), "main");
+        assert_eq!(push.sender.login, "Septias");
+        assert_eq!(
+            push.compare,
+            "https://github.com/Septias/testrepo/compare/4f2c08d6b1a9...e4f1b2a7c3d9"
+        );
+        assert_eq!(push.commits.len(), 2);
+        assert_eq!(push.commits[1].message, "Fix typo in readme");
+        assert!(push.paths().is_empty());
+    }
+}
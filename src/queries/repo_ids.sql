@@ -1 +1 @@
-SELECT repo_id FROM repo;
\ No newline at end of file
+SELECT repo_id FROM repo;
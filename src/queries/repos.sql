@@ -1 +1 @@
-SELECT id FROM repo;
\ No newline at end of file
+SELECT repo_id, owner, name, url FROM repo;
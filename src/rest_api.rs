@@ -1,11 +1,19 @@
 //! Integration for Githubs Rest API
 
 use anyhow::bail;
-use log::{error, info};
-use serde::Deserialize;
+use log::info;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
 use thiserror::Error;
 
-use crate::{shared::Repository, PORT};
+use crate::{
+    shared::{
+        issue::Issue,
+        pr::{Branch, PR},
+        Label, Repository, User,
+    },
+    PORT,
+};
 
 #[derive(Error, Debug)]
 pub enum HookError {
@@ -21,32 +29,119 @@ struct CreatedResponse {
     pub id: usize,
 }
 
-pub async fn create_hook(owner: &str, repo: &str, key: &str, ip: &str) -> anyhow::Result<usize> {
+/// A repository webhook as returned by the REST-Api
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+pub struct Hook {
+    pub id: usize,
+    pub active: bool,
+    pub events: Vec<String>,
+    pub config: HookConfig,
+    pub last_response: Option<LastResponse>,
+}
+
+/// Response the hook got for its last delivery
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+pub struct LastResponse {
+    pub code: Option<u16>,
+    pub status: Option<String>,
+    pub message: Option<String>,
+}
+
+/// One delivery attempt of a webhook
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+pub struct Delivery {
+    pub id: usize,
+    pub guid: String,
+    pub delivered_at: String,
+    pub redelivery: bool,
+    pub status: String,
+    pub status_code: u16,
+    pub event: String,
+    pub action: Option<String>,
+}
+
+impl Delivery {
+    pub fn succeeded(&self) -> bool {
+        (200..300).contains(&self.status_code)
+    }
+}
+
+/// Configuration of a webhook
+///
+/// Github never returns the secret itself but only `********` if one is set.
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+pub struct HookConfig {
+    pub url: Option<String>,
+    pub secret: Option<String>,
+}
+
+impl Hook {
+    /// Check whether the hook delivers other events than the wanted ones
+    ///
+    /// A hook without any wanted events is expected to be inactive.
+    pub fn events_differ(&self, events: &[&str]) -> bool {
+        if events.is_empty() {
+            return self.active;
+        }
+        let mut current = self.events.iter().map(String::as_str).collect::<Vec<_>>();
+        let mut wanted = events.to_vec();
+        current.sort_unstable();
+        wanted.sort_unstable();
+        !self.active || current != wanted
+    }
+}
+
+/// Url under which the [crate::server::Server] receives webhooks
+pub fn receive_url(ip: &str) -> String {
+    format!("http://{ip}:{PORT}/receive")
+}
+
+fn hook_config(ip: &str, secret: Option<&str>) -> serde_json::Value {
+    let mut config = json!({
+        "url": receive_url(ip),
+        "content_type": "json",
+        "insecure_ssl": "0"
+    });
+    if let Some(secret) = secret {
+        config["secret"] = json!(secret);
+    }
+    config
+}
+
+/// Request body to create or update a hook
+///
+/// Github needs at least one event, so hooks nobody listens to get deactivated instead.
+fn hook_body(ip: &str, events: &[&str], secret: Option<&str>) -> serde_json::Value {
+    let mut body = json!({
+        "active": !events.is_empty(),
+        "config": hook_config(ip, secret)
+    });
+    if !events.is_empty() {
+        body["events"] = json!(events);
+    }
+    body
+}
+
+pub async fn create_hook(
+    owner: &str,
+    repo: &str,
+    key: &str,
+    ip: &str,
+    events: &[&str],
+    secret: Option<&str>,
+) -> anyhow::Result<usize> {
     let client = reqwest::Client::new();
     let url = format!("https://api.github.com/repos/{owner}/{repo}/hooks");
 
     info!("creating webhook at <{url}> with ip=<{ip}>");
+    let mut body = hook_body(ip, events, secret);
+    body["name"] = json!("web");
     let res = client
         .post(&url)
         .header("Accept", "application/vnd.github+json")
         .header("Authorization", format!("Bearer {key}"))
         .header("User-Agent", "deltachat-github-bot")
-        .body(format!(
-            r#"
-{{
-    "name": "web",
-    "active": true,
-    "events": [
-        "issues",
-        "pull_request"
-    ],
-    "config": {{
-        "url": "http://{ip}:{PORT}/receive",
-        "content_type": "json",
-        "insecure_ssl": "0"
-    }}
-}}"#,
-        ))
+        .body(body.to_string())
         .send()
         .await?;
 
@@ -61,6 +156,111 @@ pub async fn create_hook(owner: &str, repo: &str, key: &str, ip: &str) -> anyhow
     }
 }
 
+/// Create a webhook or reuse the one that already points to this bot
+///
+/// Github rejects a second hook with the same url, which happens after
+/// losing the database or when several bots share one host.
+pub async fn create_or_adopt_hook(
+    owner: &str,
+    repo: &str,
+    key: &str,
+    ip: &str,
+    events: &[&str],
+    secret: Option<&str>,
+) -> anyhow::Result<usize> {
+    match create_hook(owner, repo, key, ip, events, secret).await {
+        Err(err)
+            if matches!(
+                err.downcast_ref::<HookError>(),
+                Some(HookError::ValidationError)
+            ) =>
+        {
+            let receive_url = receive_url(ip);
+            let hook = list_hooks(owner, repo, key)
+                .await?
+                .into_iter()
+                .find(|hook| hook.config.url.as_deref() == Some(receive_url.as_str()))
+                .ok_or(err)?;
+            info!("adopting existing webhook {} of {owner}/{repo}", hook.id);
+            // the secret can't be compared because github hides it, so always renew it
+            if hook.events_differ(events) || secret.is_some() || hook.config.secret.is_some() {
+                update_hook(owner, repo, hook.id, key, ip, events, secret).await?;
+            }
+            Ok(hook.id)
+        }
+        other => other,
+    }
+}
+
+/// List all webhooks of a repository
+pub async fn list_hooks(owner: &str, repo: &str, key: &str) -> anyhow::Result<Vec<Hook>> {
+    let client = reqwest::Client::new();
+    let url = format!("https://api.github.com/repos/{owner}/{repo}/hooks?per_page=100");
+    let res = client
+        .get(&url)
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .send()
+        .await?;
+    if res.status() == 200 {
+        Ok(serde_json::from_str::<Vec<Hook>>(&res.text().await?)?)
+    } else {
+        Err(HookError::Server(res.status().to_string()))?
+    }
+}
+
+/// Get a single webhook of a repository
+pub async fn get_hook(owner: &str, repo: &str, hook: usize, key: &str) -> anyhow::Result<Hook> {
+    let client = reqwest::Client::new();
+    let url = format!("https://api.github.com/repos/{owner}/{repo}/hooks/{hook}");
+    let res = client
+        .get(&url)
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .send()
+        .await?;
+    if res.status() == 200 {
+        Ok(serde_json::from_str::<Hook>(&res.text().await?)?)
+    } else {
+        Err(HookError::Server(res.status().to_string()))?
+    }
+}
+
+/// Change events and secret of an existing webhook
+pub async fn update_hook(
+    owner: &str,
+    repo: &str,
+    hook: usize,
+    key: &str,
+    ip: &str,
+    events: &[&str],
+    secret: Option<&str>,
+) -> anyhow::Result<()> {
+    let client = reqwest::Client::new();
+    let url = format!("https://api.github.com/repos/{owner}/{repo}/hooks/{hook}");
+
+    info!("updating webhook at <{url}> with events {events:?}");
+    let res = client
+        .patch(&url)
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .body(hook_body(ip, events, secret).to_string())
+        .send()
+        .await?;
+
+    let status = res.status();
+    if status == 200 {
+        Ok(())
+    } else if status == 422 {
+        Err(HookError::ValidationError)?
+    } else {
+        Err(HookError::Server(status.to_string()))?
+    }
+}
+
 pub async fn remove_hook(owner: &str, repo: &str, hook: usize, key: &str) -> anyhow::Result<()> {
     let client = reqwest::Client::new();
     let url = format!("https://api.github.com/repos/{owner}/{repo}/hooks/{hook}");
@@ -78,6 +278,131 @@ pub async fn remove_hook(owner: &str, repo: &str, hook: usize, key: &str) -> any
     }
 }
 
+/// Get the most recent deliveries of a webhook
+pub async fn get_deliveries(
+    owner: &str,
+    repo: &str,
+    hook: usize,
+    key: &str,
+) -> anyhow::Result<Vec<Delivery>> {
+    let client = reqwest::Client::new();
+    let url =
+        format!("https://api.github.com/repos/{owner}/{repo}/hooks/{hook}/deliveries?per_page=50");
+    let res = client
+        .get(&url)
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .send()
+        .await?;
+    if res.status() == 200 {
+        Ok(serde_json::from_str::<Vec<Delivery>>(&res.text().await?)?)
+    } else {
+        Err(HookError::Server(res.status().to_string()))?
+    }
+}
+
+/// Ask github to send a delivery again
+pub async fn redeliver(
+    owner: &str,
+    repo: &str,
+    hook: usize,
+    delivery: usize,
+    key: &str,
+) -> anyhow::Result<()> {
+    let client = reqwest::Client::new();
+    let url = format!(
+        "https://api.github.com/repos/{owner}/{repo}/hooks/{hook}/deliveries/{delivery}/attempts"
+    );
+    let res = client
+        .post(&url)
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .send()
+        .await?;
+    if res.status() == 202 {
+        Ok(())
+    } else {
+        Err(HookError::Server(res.status().to_string()))?
+    }
+}
+
+#[derive(Deserialize)]
+struct PRFile {
+    filename: String,
+}
+
+/// Get the paths of all files changed by a pull request
+pub async fn get_pr_files(
+    owner: &str,
+    repo: &str,
+    number: usize,
+    key: &str,
+) -> anyhow::Result<Vec<String>> {
+    let client = reqwest::Client::new();
+    let mut files = vec![];
+    // github lists at most 3000 files in pages of 100
+    for page in 1..=30 {
+        let url = format!(
+            "https://api.github.com/repos/{owner}/{repo}/pulls/{number}/files\
+             ?per_page=100&page={page}"
+        );
+        let res = client
+            .get(&url)
+            .header("Accept", "application/vnd.github+json")
+            .header("Authorization", format!("Bearer {key}"))
+            .header("User-Agent", "deltachat-github-bot")
+            .send()
+            .await?;
+        if res.status() != 200 {
+            Err(HookError::Server(res.status().to_string()))?
+        }
+        let page = serde_json::from_str::<Vec<PRFile>>(&res.text().await?)?;
+        let last = page.len() < 100;
+        files.extend(page.into_iter().map(|file| file.filename));
+        if last {
+            break;
+        }
+    }
+    Ok(files)
+}
+
+/// One entry of the event timeline of a repository
+#[derive(Deserialize, Debug)]
+pub struct RepoEvent {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub actor: User,
+    pub payload: serde_json::Value,
+    pub created_at: String,
+    /// Whether the repository was public when the event happened
+    #[serde(default)]
+    pub public: bool,
+}
+
+/// Get the events of a repository that are newer than the event with id `since`, newest first
+///
+/// Without `since` only the most recent page is fetched.
+/// Returns `None` if nothing changed since the response with the given etag.
+pub async fn get_repo_events(
+    owner: &str,
+    repo: &str,
+    key: &str,
+    etag: Option<&str>,
+    since: Option<u64>,
+) -> anyhow::Result<Option<Pages<RepoEvent>>> {
+    let url = format!("https://api.github.com/repos/{owner}/{repo}/events?per_page=100");
+    let max_pages = if since.is_some() { MAX_PAGES } else { 1 };
+    get_pages(&url, key, etag, max_pages, |event: &RepoEvent| {
+        since.map_or(false, |since| {
+            event.id.parse::<u64>().map_or(false, |id| id <= since)
+        })
+    })
+    .await
+}
+
 pub async fn get_repository(owner: &str, repo: &str, key: &str) -> anyhow::Result<Repository> {
     let client = reqwest::Client::new();
     let url = format!("https://api.github.com/repos/{owner}/{repo}");
@@ -91,6 +416,782 @@ pub async fn get_repository(owner: &str, repo: &str, key: &str) -> anyhow::Resul
     Ok(serde_json::from_str::<Repository>(&res.text().await?)?)
 }
 
+/// Check whether a repository is private
+pub async fn is_private(owner: &str, repo: &str, key: &str) -> anyhow::Result<bool> {
+    #[derive(Deserialize)]
+    struct Visibility {
+        private: bool,
+    }
+
+    let client = reqwest::Client::new();
+    let url = format!("https://api.github.com/repos/{owner}/{repo}");
+    let res = client
+        .get(&url)
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .send()
+        .await?;
+    if res.status() == 200 {
+        Ok(serde_json::from_str::<Visibility>(&res.text().await?)?.private)
+    } else {
+        Err(HookError::Server(res.status().to_string()))?
+    }
+}
+
+/// Get the user a token belongs to
+pub async fn get_authenticated_user(key: &str) -> anyhow::Result<User> {
+    let client = reqwest::Client::new();
+    let res = client
+        .get("https://api.github.com/user")
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .send()
+        .await?;
+    if res.status() == 200 {
+        Ok(serde_json::from_str::<User>(&res.text().await?)?)
+    } else {
+        Err(HookError::Server(res.status().to_string()))?
+    }
+}
+
+/// Check that a github login only has the characters github allows
+pub fn is_valid_login(login: &str) -> bool {
+    !login.is_empty() && login.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// A gist of a user
+#[derive(Deserialize, Debug)]
+pub struct Gist {
+    pub description: Option<String>,
+}
+
+/// Get the most recent public gists of a user, which doesn't need a token
+pub async fn get_gists(login: &str) -> anyhow::Result<Vec<Gist>> {
+    if !is_valid_login(login) {
+        bail!("invalid github login {login}");
+    }
+    let client = reqwest::Client::new();
+    let url = format!("https://api.github.com/users/{login}/gists");
+    let res = client
+        .get(&url)
+        .header("Accept", "application/vnd.github+json")
+        .header("User-Agent", "deltachat-github-bot")
+        .send()
+        .await?;
+    if res.status() == 200 {
+        Ok(serde_json::from_str::<Vec<Gist>>(&res.text().await?)?)
+    } else {
+        Err(HookError::Server(res.status().to_string()))?
+    }
+}
+
+/// An issue that was just opened
+#[derive(Deserialize, Debug)]
+pub struct NewIssue {
+    pub number: usize,
+    pub html_url: String,
+}
+
+/// Open an issue
+pub async fn create_issue(
+    owner: &str,
+    repo: &str,
+    key: &str,
+    title: &str,
+    body: &str,
+    labels: &[String],
+    assignees: &[String],
+) -> anyhow::Result<NewIssue> {
+    let client = reqwest::Client::new();
+    let url = format!("https://api.github.com/repos/{owner}/{repo}/issues");
+    let res = client
+        .post(&url)
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .body(
+            json!({
+                "title": title,
+                "body": body,
+                "labels": labels,
+                "assignees": assignees,
+            })
+            .to_string(),
+        )
+        .send()
+        .await?;
+
+    let status = res.status();
+    if status == 201 {
+        Ok(serde_json::from_str::<NewIssue>(&res.text().await?)?)
+    } else if status == 422 {
+        Err(HookError::ValidationError)?
+    } else {
+        Err(HookError::Server(status.to_string()))?
+    }
+}
+
+/// A comment on an issue or pull request
+#[derive(Deserialize, Debug)]
+pub struct Comment {
+    pub id: usize,
+    pub html_url: String,
+}
+
+/// Comment on an issue or pull request
+pub async fn create_comment(
+    owner: &str,
+    repo: &str,
+    number: usize,
+    key: &str,
+    body: &str,
+) -> anyhow::Result<Comment> {
+    let client = reqwest::Client::new();
+    let url = format!("https://api.github.com/repos/{owner}/{repo}/issues/{number}/comments");
+    let res = client
+        .post(&url)
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .body(json!({ "body": body }).to_string())
+        .send()
+        .await?;
+
+    let status = res.status();
+    if status == 201 {
+        Ok(serde_json::from_str::<Comment>(&res.text().await?)?)
+    } else if status == 422 {
+        Err(HookError::ValidationError)?
+    } else {
+        Err(HookError::Server(status.to_string()))?
+    }
+}
+
+/// An existing issue or pull request, as returned by the issues endpoints
+#[derive(Deserialize, Debug)]
+pub struct IssueItem {
+    pub number: usize,
+    pub title: String,
+    pub html_url: String,
+    pub body: Option<String>,
+    pub state: String,
+    pub state_reason: Option<String>,
+    pub user: User,
+    pub labels: Vec<Label>,
+    pub assignees: Vec<User>,
+    /// Only set for pull requests
+    pub pull_request: Option<serde_json::Value>,
+}
+
+impl IssueItem {
+    pub fn is_pull_request(&self) -> bool {
+        self.pull_request.is_some()
+    }
+}
+
+/// URL of an issue or of something below it, with every part percent-encoded
+fn issue_url(
+    owner: &str,
+    repo: &str,
+    number: usize,
+    path: &[&str],
+) -> anyhow::Result<reqwest::Url> {
+    let mut url = reqwest::Url::parse("https://api.github.com")?;
+    url.path_segments_mut()
+        .map_err(|_| anyhow::anyhow!("api url can't have a path"))?
+        .extend(["repos", owner, repo, "issues", number.to_string().as_str()])
+        .extend(path);
+    Ok(url)
+}
+
+/// Get an issue or pull request
+pub async fn get_issue(
+    owner: &str,
+    repo: &str,
+    number: usize,
+    key: &str,
+) -> anyhow::Result<IssueItem> {
+    let client = reqwest::Client::new();
+    let res = client
+        .get(issue_url(owner, repo, number, &[])?)
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .send()
+        .await?;
+    if res.status() == 200 {
+        Ok(serde_json::from_str::<IssueItem>(&res.text().await?)?)
+    } else {
+        Err(HookError::Server(res.status().to_string()))?
+    }
+}
+
+/// Close or reopen an issue or pull request
+///
+/// The reason is only used for issues and is one of `completed` and `not_planned`.
+pub async fn set_issue_state(
+    owner: &str,
+    repo: &str,
+    number: usize,
+    key: &str,
+    state: &str,
+    reason: Option<&str>,
+) -> anyhow::Result<IssueItem> {
+    let client = reqwest::Client::new();
+    let mut body = json!({ "state": state });
+    if let Some(reason) = reason {
+        body["state_reason"] = json!(reason);
+    }
+    let res = client
+        .patch(issue_url(owner, repo, number, &[])?)
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .body(body.to_string())
+        .send()
+        .await?;
+
+    let status = res.status();
+    if status == 200 {
+        Ok(serde_json::from_str::<IssueItem>(&res.text().await?)?)
+    } else if status == 422 {
+        Err(HookError::ValidationError)?
+    } else {
+        Err(HookError::Server(status.to_string()))?
+    }
+}
+
+/// Add labels to an issue or pull request, unknown labels are created
+pub async fn add_labels(
+    owner: &str,
+    repo: &str,
+    number: usize,
+    key: &str,
+    labels: &[String],
+) -> anyhow::Result<()> {
+    let client = reqwest::Client::new();
+    let res = client
+        .post(issue_url(owner, repo, number, &["labels"])?)
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .body(json!({ "labels": labels }).to_string())
+        .send()
+        .await?;
+
+    let status = res.status();
+    if status == 200 {
+        Ok(())
+    } else if status == 422 {
+        Err(HookError::ValidationError)?
+    } else {
+        Err(HookError::Server(status.to_string()))?
+    }
+}
+
+/// Remove a label from an issue or pull request, returns false if it wasn't set
+pub async fn remove_label(
+    owner: &str,
+    repo: &str,
+    number: usize,
+    key: &str,
+    label: &str,
+) -> anyhow::Result<bool> {
+    let client = reqwest::Client::new();
+    let res = client
+        .delete(issue_url(owner, repo, number, &["labels", label])?)
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .send()
+        .await?;
+    if res.status() == 200 {
+        Ok(true)
+    } else if res.status() == 404 {
+        // the label was removed in the meantime
+        Ok(false)
+    } else {
+        Err(HookError::Server(res.status().to_string()))?
+    }
+}
+
+/// Assign users to an issue or pull request
+///
+/// Github silently skips users that can't be assigned, so the returned item
+/// has to be checked for who actually got assigned.
+pub async fn add_assignees(
+    owner: &str,
+    repo: &str,
+    number: usize,
+    key: &str,
+    assignees: &[String],
+) -> anyhow::Result<IssueItem> {
+    let client = reqwest::Client::new();
+    let res = client
+        .post(issue_url(owner, repo, number, &["assignees"])?)
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .body(json!({ "assignees": assignees }).to_string())
+        .send()
+        .await?;
+
+    let status = res.status();
+    if status == 201 {
+        Ok(serde_json::from_str::<IssueItem>(&res.text().await?)?)
+    } else if status == 422 {
+        Err(HookError::ValidationError)?
+    } else {
+        Err(HookError::Server(status.to_string()))?
+    }
+}
+
+/// Most pages of 100 items fetched for a list
+const MAX_PAGES: usize = 10;
+
+/// Items of a paginated list together with the etag of its first page
+pub struct Pages<T> {
+    pub items: Vec<T>,
+    pub etag: Option<String>,
+    /// Whether an item matched the stop condition before the pages ran out
+    pub reached: bool,
+    /// Whether more pages were left when the page limit was hit
+    pub truncated: bool,
+    /// Number of items read from the pages, before callers filtered any out
+    pub fetched: usize,
+}
+
+/// Get the items of a paginated list by following the `next` links until an item matches `until`
+///
+/// The matching item and all items after it are left out.
+/// Returns `None` if the first page didn't change since the response with the given etag.
+async fn get_pages<T: DeserializeOwned>(
+    url: &str,
+    key: &str,
+    etag: Option<&str>,
+    max_pages: usize,
+    until: impl Fn(&T) -> bool,
+) -> anyhow::Result<Option<Pages<T>>> {
+    let client = reqwest::Client::new();
+    let mut pages = Pages {
+        items: vec![],
+        etag: None,
+        reached: false,
+        truncated: false,
+        fetched: 0,
+    };
+    let mut next = Some(url.to_string());
+    for page in 0..max_pages {
+        let Some(url) = next.take() else {
+            break;
+        };
+        let mut req = client
+            .get(&url)
+            .header("Accept", "application/vnd.github+json")
+            .header("Authorization", format!("Bearer {key}"))
+            .header("User-Agent", "deltachat-github-bot");
+        if let (0, Some(etag)) = (page, etag) {
+            req = req.header("If-None-Match", etag);
+        }
+        let res = req.send().await?;
+        if page == 0 && res.status() == 304 {
+            return Ok(None);
+        }
+        if res.status() != 200 {
+            Err(HookError::Server(res.status().to_string()))?
+        }
+        if page == 0 {
+            pages.etag = res
+                .headers()
+                .get("ETag")
+                .and_then(|etag| etag.to_str().ok())
+                .map(str::to_string);
+        }
+        next = res
+            .headers()
+            .get("Link")
+            .and_then(|link| link.to_str().ok())
+            .and_then(next_link);
+        for item in serde_json::from_str::<Vec<T>>(&res.text().await?)? {
+            if until(&item) {
+                pages.reached = true;
+                return Ok(Some(pages));
+            }
+            pages.items.push(item);
+            pages.fetched += 1;
+        }
+    }
+    pages.truncated = next.is_some();
+    Ok(Some(pages))
+}
+
+/// Get the items of a paginated list up to the page limit by following the `next` links
+async fn get_list<T: DeserializeOwned>(url: &str, key: &str) -> anyhow::Result<Pages<T>> {
+    let pages = get_pages(url, key, None, MAX_PAGES, |_| false).await?;
+    Ok(pages.unwrap_or(Pages {
+        items: vec![],
+        etag: None,
+        reached: false,
+        truncated: false,
+        fetched: 0,
+    }))
+}
+
+/// Get every item of a paginated list by following the `next` links
+async fn get_all_pages<T: DeserializeOwned>(url: &str, key: &str) -> anyhow::Result<Vec<T>> {
+    Ok(get_list(url, key).await?.items)
+}
+
+/// The url of the next page in a `Link` header like `<url>; rel="next", <url>; rel="last"`
+fn next_link(header: &str) -> Option<String> {
+    header.split(',').find_map(|link| {
+        let (url, params) = link.split_once(';')?;
+        params
+            .split(';')
+            .any(|param| param.trim() == r#"rel="next""#)
+            .then(|| {
+                url.trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_string()
+            })
+    })
+}
+
+/// Get the open issues of a repository, without pull requests
+pub async fn list_issues(
+    owner: &str,
+    repo: &str,
+    key: &str,
+    labels: &[String],
+    assignee: Option<&str>,
+) -> anyhow::Result<Pages<IssueItem>> {
+    let mut url = reqwest::Url::parse(&format!(
+        "https://api.github.com/repos/{owner}/{repo}/issues?state=open&per_page=100"
+    ))?;
+    if !labels.is_empty() {
+        url.query_pairs_mut()
+            .append_pair("labels", &labels.join(","));
+    }
+    if let Some(assignee) = assignee {
+        url.query_pairs_mut().append_pair("assignee", assignee);
+    }
+    let mut issues = get_list::<IssueItem>(url.as_str(), key).await?;
+    issues.items.retain(|issue| !issue.is_pull_request());
+    Ok(issues)
+}
+
+/// An issue or pull request as listed by the issues endpoint, with the time it was opened
+#[derive(Deserialize, Debug)]
+pub struct UpdatedIssue {
+    #[serde(flatten)]
+    pub issue: Issue,
+    pub created_at: String,
+    /// Only set for pull requests
+    pub pull_request: Option<serde_json::Value>,
+}
+
+/// Get the issues and pull requests of a repository that were updated since the given time
+pub async fn list_updated_issues(
+    owner: &str,
+    repo: &str,
+    key: &str,
+    since: &str,
+) -> anyhow::Result<Vec<UpdatedIssue>> {
+    let mut url = reqwest::Url::parse(&format!(
+        "https://api.github.com/repos/{owner}/{repo}/issues?state=all&per_page=100"
+    ))?;
+    url.query_pairs_mut().append_pair("since", since);
+    get_all_pages(url.as_str(), key).await
+}
+
+/// A pull request as listed by the pulls endpoint
+#[derive(Deserialize, Debug)]
+pub struct PullItem {
+    pub number: usize,
+    pub title: String,
+    pub html_url: String,
+    pub draft: bool,
+    pub user: User,
+    pub labels: Vec<Label>,
+    pub requested_reviewers: Vec<User>,
+}
+
+/// Get the open pull requests of a repository
+pub async fn list_prs(owner: &str, repo: &str, key: &str) -> anyhow::Result<Pages<PullItem>> {
+    let url = format!("https://api.github.com/repos/{owner}/{repo}/pulls?state=open&per_page=100");
+    get_list(&url, key).await
+}
+
+/// Get a pull request
+pub async fn get_pr(owner: &str, repo: &str, number: usize, key: &str) -> anyhow::Result<PR> {
+    let client = reqwest::Client::new();
+    let url = format!("https://api.github.com/repos/{owner}/{repo}/pulls/{number}");
+    let res = client
+        .get(&url)
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .send()
+        .await?;
+    if res.status() == 200 {
+        Ok(serde_json::from_str::<PR>(&res.text().await?)?)
+    } else {
+        Err(HookError::Server(res.status().to_string()))?
+    }
+}
+
+/// Whether and how a pull request can be merged
+#[derive(Deserialize, Debug)]
+pub struct Mergeability {
+    pub state: String,
+    pub merged: bool,
+    pub draft: bool,
+    /// `None` while github is still computing it
+    pub mergeable: Option<bool>,
+    /// e.g. `clean`, `dirty` for conflicts or `blocked` by branch protection
+    pub mergeable_state: String,
+    pub head: Branch,
+}
+
+/// Get whether a pull request can be merged
+pub async fn get_mergeability(
+    owner: &str,
+    repo: &str,
+    number: usize,
+    key: &str,
+) -> anyhow::Result<Mergeability> {
+    let client = reqwest::Client::new();
+    let url = format!("https://api.github.com/repos/{owner}/{repo}/pulls/{number}");
+    let res = client
+        .get(&url)
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .send()
+        .await?;
+    if res.status() == 200 {
+        Ok(serde_json::from_str::<Mergeability>(&res.text().await?)?)
+    } else {
+        Err(HookError::Server(res.status().to_string()))?
+    }
+}
+
+/// Approve a pull request, optionally with a comment
+pub async fn approve_pr(
+    owner: &str,
+    repo: &str,
+    number: usize,
+    key: &str,
+    comment: &str,
+) -> anyhow::Result<()> {
+    let client = reqwest::Client::new();
+    let url = format!("https://api.github.com/repos/{owner}/{repo}/pulls/{number}/reviews");
+    let mut body = json!({ "event": "APPROVE" });
+    if !comment.is_empty() {
+        body["body"] = json!(comment);
+    }
+    let res = client
+        .post(&url)
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .body(body.to_string())
+        .send()
+        .await?;
+
+    let status = res.status();
+    if status == 200 {
+        Ok(())
+    } else if status == 422 {
+        Err(HookError::ValidationError)?
+    } else {
+        Err(HookError::Server(status.to_string()))?
+    }
+}
+
+#[derive(Deserialize)]
+struct ApiMessage {
+    message: String,
+}
+
+/// Merge a pull request if its head is still at `sha` and return the merge commit
+///
+/// `method` is one of `merge`, `squash` and `rebase`.
+pub async fn merge_pr(
+    owner: &str,
+    repo: &str,
+    number: usize,
+    key: &str,
+    method: &str,
+    sha: &str,
+) -> anyhow::Result<String> {
+    #[derive(Deserialize)]
+    struct Merged {
+        sha: String,
+    }
+
+    let client = reqwest::Client::new();
+    let url = format!("https://api.github.com/repos/{owner}/{repo}/pulls/{number}/merge");
+    let res = client
+        .put(&url)
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .body(json!({ "merge_method": method, "sha": sha }).to_string())
+        .send()
+        .await?;
+
+    let status = res.status();
+    if status == 200 {
+        Ok(serde_json::from_str::<Merged>(&res.text().await?)?.sha)
+    } else if status == 405 || status == 409 {
+        // not mergeable or the head changed, github explains why
+        let message = serde_json::from_str::<ApiMessage>(&res.text().await?)?.message;
+        bail!("Github refused to merge: {message}")
+    } else if status == 422 {
+        Err(HookError::ValidationError)?
+    } else {
+        Err(HookError::Server(status.to_string()))?
+    }
+}
+
+/// A review of a pull request
+#[derive(Deserialize, Debug)]
+pub struct Review {
+    pub user: User,
+    /// `APPROVED`, `CHANGES_REQUESTED`, `COMMENTED`, `DISMISSED` or `PENDING`
+    pub state: String,
+}
+
+/// Get the reviews of a pull request, oldest first
+pub async fn get_reviews(
+    owner: &str,
+    repo: &str,
+    number: usize,
+    key: &str,
+) -> anyhow::Result<Vec<Review>> {
+    let client = reqwest::Client::new();
+    let url =
+        format!("https://api.github.com/repos/{owner}/{repo}/pulls/{number}/reviews?per_page=100");
+    let res = client
+        .get(&url)
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .send()
+        .await?;
+    if res.status() == 200 {
+        Ok(serde_json::from_str::<Vec<Review>>(&res.text().await?)?)
+    } else {
+        Err(HookError::Server(res.status().to_string()))?
+    }
+}
+
+/// A CI check run on a commit
+#[derive(Deserialize, Debug)]
+pub struct CheckRun {
+    pub name: String,
+    /// `queued`, `in_progress` or `completed`
+    pub status: String,
+    /// Only set for completed runs, e.g. `success` or `failure`
+    pub conclusion: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CheckRuns {
+    check_runs: Vec<CheckRun>,
+}
+
+/// Get the check runs of a commit
+pub async fn get_check_runs(
+    owner: &str,
+    repo: &str,
+    sha: &str,
+    key: &str,
+) -> anyhow::Result<Vec<CheckRun>> {
+    let client = reqwest::Client::new();
+    let url = format!(
+        "https://api.github.com/repos/{owner}/{repo}/commits/{sha}/check-runs?per_page=100"
+    );
+    let res = client
+        .get(&url)
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {key}"))
+        .header("User-Agent", "deltachat-github-bot")
+        .send()
+        .await?;
+    if res.status() == 200 {
+        Ok(serde_json::from_str::<CheckRuns>(&res.text().await?)?.check_runs)
+    } else {
+        Err(HookError::Server(res.status().to_string()))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_issue_url() {
+        assert_eq!(
+            issue_url("Septias", "testrepo", 3, &["labels", "good first issue"])
+                .unwrap()
+                .as_str(),
+            "https://api.github.com/repos/Septias/testrepo/issues/3/labels/good%20first%20issue"
+        );
+    }
+
+    #[test]
+    fn test_is_valid_login() {
+        assert!(is_valid_login("Septias"));
+        assert!(is_valid_login("link2xt"));
+        assert!(is_valid_login("deltachat-bot"));
+        assert!(!is_valid_login(""));
+        assert!(!is_valid_login("../orgs/deltachat"));
+        assert!(!is_valid_login("septias?per_page=1"));
+    }
+
+    #[test]
+    fn test_next_link() {
+        let header = "<https://api.github.com/repositories/1/issues?page=1>; rel=\"prev\", \
+                      <https://api.github.com/repositories/1/issues?page=3>; rel=\"next\", \
+                      <https://api.github.com/repositories/1/issues?page=5>; rel=\"last\"";
+        assert_eq!(
+            next_link(header).as_deref(),
+            Some("https://api.github.com/repositories/1/issues?page=3")
+        );
+        assert_eq!(
+            next_link("<https://api.github.com/repositories/1/issues?page=1>; rel=\"first\""),
+            None
+        );
+    }
+
+    #[test]
+    fn test_hook_created() {
+        let mock = include_str!("../mock/hook_created.json");
+        let hook = serde_json::from_str::<Hook>(mock).unwrap();
+        assert_eq!(hook.id, 386705382);
+        assert_eq!(
+            hook.config.url.as_deref(),
+            Some("http://188.68.57.24:8080/receive")
+        );
+        assert!(!hook.events_differ(&["pull_request", "issues"]));
+        assert!(hook.events_differ(&["issues"]));
+        assert!(hook.events_differ(&[]));
+        assert_eq!(
+            hook.last_response,
+            Some(LastResponse {
+                code: None,
+                status: Some("unused".to_string()),
+                message: None
+            })
+        );
+    }
+}
+
 /*
 // at some point it would be nice to have tests here
 
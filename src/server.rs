@@ -1,13 +1,17 @@
 //! Local server to receive Githubs webhooks
-use anyhow::anyhow;
-use log::{error, info};
-use std::sync::Arc;
+use anyhow::{anyhow, Context as _};
+use hmac::{Hmac, Mac};
+use log::{error, info, warn};
+use sha2::Sha256;
+use std::{sync::Arc, time::Duration};
 use thiserror::Error;
-use tide::{Request, Server as TideServer};
+use tide::{Request, Response, Server as TideServer, StatusCode};
 use tokio::sync::mpsc::Sender;
 
 use crate::{
-    shared::{issue::IssueEvent, pr::PREvent, WebhookEvent},
+    shared::{
+        comment::CommentEvent, issue::IssueEvent, pr::PREvent, push::PushEvent, WebhookEvent,
+    },
     PORT,
 };
 
@@ -23,9 +27,34 @@ enum Error {
     Other(#[from] anyhow::Error),
 }
 
+/// Directory of chat attachments which are linked from issues
+pub const ATTACHMENTS_DIR: &str = "attachments";
+
+/// How long attachments are served before they are deleted
+pub const ATTACHMENT_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Public url of a file in the attachments directory, below the base url of the server
+pub fn attachment_url(base: &str, name: &str) -> String {
+    format!("{}/{ATTACHMENTS_DIR}/{name}", base.trim_end_matches('/'))
+}
+
+/// Delete the attachments that are older than [ATTACHMENT_RETENTION]
+pub async fn remove_old_attachments() -> anyhow::Result<()> {
+    let mut entries = tokio::fs::read_dir(ATTACHMENTS_DIR).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let modified = entry.metadata().await?.modified()?;
+        if modified.elapsed().unwrap_or_default() > ATTACHMENT_RETENTION {
+            tokio::fs::remove_file(entry.path()).await?;
+        }
+    }
+    Ok(())
+}
+
 #[derive(Clone)]
 pub struct ServerState {
     pub channel: Arc<Sender<WebhookEvent>>,
+    /// Secret github signs deliveries with, unsigned ones are rejected if it is set
+    pub secret: Option<Arc<String>>,
 }
 
 pub struct Server {
@@ -33,7 +62,17 @@ pub struct Server {
 }
 
 async fn handler(mut req: Request<ServerState>) -> tide::Result {
-    match receive_webhoook(&mut req).await {
+    let body = req.body_bytes().await?;
+    if let Some(secret) = &req.state().secret {
+        let signature = req
+            .header("X-Hub-Signature-256")
+            .map(|header| header.as_str());
+        if !verify_signature(secret, &body, signature) {
+            warn!("rejected webhook with a missing or wrong signature");
+            return Ok(Response::new(StatusCode::Unauthorized));
+        }
+    }
+    match receive_webhoook(&req, &body) {
         Ok(event) => {
             info!("received webhook");
             req.state().channel.send(event).await.unwrap();
@@ -48,12 +87,24 @@ async fn get_handler(_req: Request<ServerState>) -> tide::Result {
 }
 
 impl Server {
-    pub fn new(channel: Sender<WebhookEvent>) -> Self {
+    /// Create the server, attachments are only served if `serve_attachments` is set
+    pub async fn new(
+        channel: Sender<WebhookEvent>,
+        secret: Option<String>,
+        serve_attachments: bool,
+    ) -> anyhow::Result<Self> {
         let mut server = tide::with_state(ServerState {
             channel: Arc::new(channel),
+            secret: secret.map(Arc::new),
         });
         server.at("receive").post(handler).get(get_handler);
-        Self { server }
+        if serve_attachments {
+            tokio::fs::create_dir_all(ATTACHMENTS_DIR)
+                .await
+                .context("failed to create attachments folder")?;
+            server.at(ATTACHMENTS_DIR).serve_dir(ATTACHMENTS_DIR)?;
+        }
+        Ok(Self { server })
     }
 
     pub fn start(&self) -> tokio::task::JoinHandle<()> {
@@ -67,24 +118,48 @@ impl Server {
     pub fn stop(self) {}
 }
 
-async fn receive_webhoook(req: &mut Request<ServerState>) -> Result<WebhookEvent, Error> {
+fn receive_webhoook(req: &Request<ServerState>, body: &[u8]) -> Result<WebhookEvent, Error> {
     match req.header("X-GitHub-Event") {
         Some(event_type) if event_type == "issues" => Ok(WebhookEvent::Issue(
-            serde_json::from_str::<IssueEvent>(&req.body_string().await.unwrap())?,
+            serde_json::from_slice::<IssueEvent>(body)?,
         )),
-        Some(event_type) if event_type == "pull_request" => Ok(WebhookEvent::PR(
-            serde_json::from_str::<PREvent>(&req.body_string().await.unwrap())?,
+        Some(event_type) if event_type == "pull_request" => {
+            Ok(WebhookEvent::PR(serde_json::from_slice::<PREvent>(body)?))
+        }
+        Some(event_type) if event_type == "push" => Ok(WebhookEvent::Push(
+            serde_json::from_slice::<PushEvent>(body)?,
+        )),
+        Some(event_type) if event_type == "issue_comment" => Ok(WebhookEvent::Comment(
+            serde_json::from_slice::<CommentEvent>(body)?,
         )),
         Some(_) => Err(Error::NotCovered),
         None => Err(Error::Other(anyhow!("Missing header `X-GitHub-Event`"))),
     }
 }
 
+/// Check the `sha256=<hex>` signature github computes over the body with the hook secret
+fn verify_signature(secret: &str, body: &[u8], signature: Option<&str>) -> bool {
+    let Some(signature) = signature
+        .and_then(|signature| signature.strip_prefix("sha256="))
+        .and_then(|signature| hex::decode(signature).ok())
+    else {
+        return false;
+    };
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac takes keys of any size");
+    mac.update(body);
+    // compares in constant time, so the signature can't be guessed byte by byte
+    mac.verify_slice(&signature).is_ok()
+}
+
 #[cfg(test)]
 mod tests {
+    use super::verify_signature;
     use crate::shared::{
+        comment::{CommentAction, CommentEvent},
         issue::{Issue, IssueAction, IssueEvent},
-        pr::{PRAction, PREvent, PR},
+        pr::{Branch, PRAction, PREvent, PR},
+        push::PushEvent,
         Repository, User,
     };
 
@@ -101,13 +176,27 @@ mod tests {
                 repository: Repository {
                     id: 558781383,
                     name: "testrepo".to_owned(),
+                    full_name: "Septias/testrepo".to_owned(),
                     url: "https://api.github.com/repos/Septias/testrepo".to_string(),
+                    html_url: "https://github.com/Septias/testrepo".to_string(),
+                    private: false,
                 },
                 issue: Issue {
                     id: 1427422736,
+                    number: 1,
                     title: "test".to_owned(),
                     url: "https://api.github.com/repos/Septias/testrepo/issues/1".to_owned(),
-                }
+                    html_url: "https://github.com/Septias/testrepo/issues/1".to_owned(),
+                    body: None,
+                    state: "closed".to_owned(),
+                    state_reason: Some("completed".to_owned()),
+                    user: User {
+                        login: "Septias".to_owned()
+                    },
+                    labels: vec![],
+                    assignees: vec![],
+                },
+                assignee: None,
             }
         );
     }
@@ -124,37 +213,79 @@ mod tests {
                 repository: Repository {
                     id: 558781383,
                     name: "testrepo".to_owned(),
+                    full_name: "Septias/testrepo".to_owned(),
                     url: "https://api.github.com/repos/Septias/testrepo".to_string(),
+                    html_url: "https://github.com/Septias/testrepo".to_string(),
+                    private: false,
                 },
                 issue: Issue {
                     id: 1427422736,
+                    number: 1,
                     title: "test".to_owned(),
                     url: "https://api.github.com/repos/Septias/testrepo/issues/1".to_owned(),
-                }
+                    html_url: "https://github.com/Septias/testrepo/issues/1".to_owned(),
+                    body: None,
+                    state: "open".to_owned(),
+                    state_reason: None,
+                    user: User {
+                        login: "Septias".to_owned()
+                    },
+                    labels: vec![],
+                    assignees: vec![],
+                },
+                assignee: None,
             }
         );
     }
 
     #[test]
-    fn test_pr_closed() {
+    fn test_pr_merged() {
         let mock = include_str!("../mock/pr_closed.json");
         assert_eq!(
             serde_json::from_str::<PREvent>(mock).unwrap(),
             PREvent {
-                action: PRAction::Closed,
+                action: PRAction::Merged,
                 sender: User {
                     login: "Septias".to_owned()
                 },
                 repository: Repository {
                     id: 558781383,
                     name: "testrepo".to_owned(),
+                    full_name: "Septias/testrepo".to_owned(),
                     url: "https://api.github.com/repos/Septias/testrepo".to_string(),
+                    html_url: "https://github.com/Septias/testrepo".to_string(),
+                    private: false,
                 },
                 pull_request: PR {
                     id: 1103900553,
+                    number: 3,
                     title: "PR 2".to_owned(),
                     url: "https://api.github.com/repos/Septias/testrepo/pulls/3".to_owned(),
-                }
+                    html_url: "https://github.com/Septias/testrepo/pull/3".to_owned(),
+                    body: None,
+                    state: "closed".to_owned(),
+                    merged: true,
+                    merged_by: Some(User {
+                        login: "Septias".to_owned()
+                    }),
+                    merge_commit_sha: Some("6ab8654b469efe4dd32095fff6d5fe39fad8466c".to_owned()),
+                    draft: false,
+                    user: User {
+                        login: "Septias".to_owned()
+                    },
+                    labels: vec![],
+                    assignees: vec![],
+                    base: Branch {
+                        name: "main".to_owned(),
+                        sha: "99bd40fea4ba518195cdd2729f4835a915f3e4ad".to_owned()
+                    },
+                    head: Branch {
+                        name: "pr2".to_owned(),
+                        sha: "c713506efe09b7866990edc644c089846655f730".to_owned()
+                    },
+                },
+                assignee: None,
+                requested_reviewer: None,
             }
         );
     }
@@ -170,15 +301,181 @@ mod tests {
                 },
                 repository: Repository {
                     url: "https://api.github.com/repos/Septias/testrepo".to_string(),
+                    html_url: "https://github.com/Septias/testrepo".to_string(),
                     id: 558781383,
                     name: "testrepo".to_owned(),
+                    full_name: "Septias/testrepo".to_owned(),
+                    private: false,
                 },
                 pull_request: PR {
                     id: 1103900553,
+                    number: 2,
                     title: "PR 2".to_owned(),
                     url: "https://api.github.com/repos/Septias/testrepo/pulls/3".to_owned(),
-                }
+                    html_url: "https://github.com/Septias/testrepo/pull/2".to_owned(),
+                    body: None,
+                    state: "open".to_owned(),
+                    merged: false,
+                    merged_by: None,
+                    merge_commit_sha: None,
+                    draft: false,
+                    user: User {
+                        login: "Septias".to_owned()
+                    },
+                    labels: vec![],
+                    assignees: vec![],
+                    base: Branch {
+                        name: "main".to_owned(),
+                        sha: "d37b458e8a8d87eec45e0537a7ffd91e175b53af".to_owned()
+                    },
+                    head: Branch {
+                        name: "pr".to_owned(),
+                        sha: "8e3de11728ca740c24f9bf15b233312b22e1dd75".to_owned()
+                    },
+                },
+                assignee: None,
+                requested_reviewer: None,
             }
         );
     }
+
+    /// Parse the fixtures with each action github sends
+    fn with_action(mock: &str, action: &str) -> String {
+        let mut event = serde_json::from_str::<serde_json::Value>(mock).unwrap();
+        event["action"] = serde_json::Value::from(action);
+        event.to_string()
+    }
+
+    #[test]
+    fn test_issue_actions() {
+        let mock = include_str!("../mock/issue_open.json");
+        let actions = [
+            ("opened", IssueAction::Opened),
+            ("edited", IssueAction::Edited),
+            ("deleted", IssueAction::Deleted),
+            ("pinned", IssueAction::Pinned),
+            ("unpinned", IssueAction::Unpinned),
+            ("closed", IssueAction::Closed),
+            ("reopened", IssueAction::Reopened),
+            ("assigned", IssueAction::Assigned),
+            ("unassigned", IssueAction::Unassigned),
+            ("labeled", IssueAction::Labeled),
+            ("unlabeled", IssueAction::Unlabeled),
+            ("locked", IssueAction::Locked),
+            ("unlocked", IssueAction::Unlocked),
+            ("transferred", IssueAction::Transferred),
+            ("milestoned", IssueAction::Milestoned),
+            ("demilestoned", IssueAction::Demilestoned),
+            ("typed", IssueAction::Other("typed".to_string())),
+        ];
+        for (name, action) in actions {
+            let event = serde_json::from_str::<IssueEvent>(&with_action(mock, name)).unwrap();
+            assert_eq!(event.action, action);
+            assert_eq!(event.action.to_string(), name);
+            assert_eq!(serde_json::to_value(&event.action).unwrap(), name);
+        }
+    }
+
+    #[test]
+    fn test_pr_actions() {
+        let mock = include_str!("../mock/pr_opened.json");
+        let actions = [
+            ("opened", PRAction::Opened),
+            ("edited", PRAction::Edited),
+            ("closed", PRAction::Closed),
+            ("reopened", PRAction::Reopened),
+            ("assigned", PRAction::Assigned),
+            ("unassigned", PRAction::Unassigned),
+            ("review_requested", PRAction::ReviewRequested),
+            ("review_request_removed", PRAction::ReviewRequestRemoved),
+            ("labeled", PRAction::Labeled),
+            ("unlabeled", PRAction::Unlabeled),
+            ("synchronize", PRAction::Synchronize),
+            ("ready_for_review", PRAction::ReadyForReview),
+            ("converted_to_draft", PRAction::ConvertedToDraft),
+            (
+                "auto_merge_enabled",
+                PRAction::Other("auto_merge_enabled".to_string()),
+            ),
+            ("enqueued", PRAction::Other("enqueued".to_string())),
+        ];
+        for (name, action) in actions {
+            let event = serde_json::from_str::<PREvent>(&with_action(mock, name)).unwrap();
+            assert_eq!(event.action, action);
+            assert_eq!(event.action.to_string(), name);
+            assert_eq!(serde_json::to_value(&event.action).unwrap(), name);
+        }
+    }
+
+    #[test]
+    fn test_push() {
+        let mock = include_str!("../mock/push.json");
+        let event = serde_json::from_str::<PushEvent>(mock).unwrap();
+        assert_eq!(event.branch(), "main");
+        assert_eq!(event.sender.login, "Septias");
+        assert_eq!(event.commits.len(), 2);
+        assert_eq!(
+            event.paths(),
+            ["web/index.html", "web/style.css", "readme.md", "old.txt"]
+        );
+    }
+
+    #[test]
+    fn test_comment() {
+        let mock = include_str!("../mock/comment_created.json");
+        let event = serde_json::from_str::<CommentEvent>(mock).unwrap();
+        assert_eq!(event.action, CommentAction::Created);
+        assert_eq!(event.issue.number, 1);
+        assert_eq!(event.comment.user.login, "Septias");
+        assert_eq!(
+            event.comment.body,
+            "@hpk42 can you take a look? cc @link2xt"
+        );
+
+        let event = serde_json::from_str::<CommentEvent>(&with_action(mock, "pinned")).unwrap();
+        assert_eq!(event.action, CommentAction::Other("pinned".to_string()));
+    }
+
+    #[test]
+    fn test_signature() {
+        // example from github's documentation on validating deliveries
+        let secret = "It's a Secret to Everybody";
+        let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+        assert!(verify_signature(secret, b"Hello, World!", Some(signature)));
+        assert!(!verify_signature(secret, b"Hello, World?", Some(signature)));
+        assert!(!verify_signature(
+            "other secret",
+            b"Hello, World!",
+            Some(signature)
+        ));
+        assert!(!verify_signature(
+            secret,
+            b"Hello, World!",
+            Some("sha256=nonsense")
+        ));
+        assert!(!verify_signature(secret, b"Hello, World!", None));
+    }
+
+    #[test]
+    fn test_attachment_url() {
+        for base in ["https://bot.example.org", "https://bot.example.org/"] {
+            assert_eq!(
+                attachment_url(base, "3f2a-screen.png"),
+                "https://bot.example.org/attachments/3f2a-screen.png"
+            );
+        }
+    }
+
+    #[test]
+    fn test_assignee() {
+        let mut event =
+            serde_json::from_str::<serde_json::Value>(include_str!("../mock/pr_opened.json"))
+                .unwrap();
+        event["action"] = "review_requested".into();
+        event["requested_reviewer"] = serde_json::json!({ "login": "hpk42" });
+        let event = serde_json::from_str::<PREvent>(&event.to_string()).unwrap();
+        assert_eq!(event.action, PRAction::ReviewRequested);
+        assert_eq!(event.requested_reviewer.unwrap().login, "hpk42");
+        assert_eq!(event.assignee, None);
+    }
 }
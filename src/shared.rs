@@ -3,48 +3,73 @@
 use serde::{Deserialize, Serialize};
 use strum_macros::Display;
 
-use self::{issue::IssueEvent, pr::PREvent};
+use self::{comment::CommentEvent, issue::IssueEvent, pr::PREvent, push::PushEvent};
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct User {
     pub login: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Label {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Repository {
     pub id: usize,
     pub name: String,
+    pub full_name: String,
     pub url: String,
+    pub html_url: String,
+    #[serde(default)]
+    pub private: bool,
 }
 
 #[derive(Debug, Display)]
 pub enum WebhookEvent {
     Issue(IssueEvent),
     PR(PREvent),
+    Push(PushEvent),
+    Comment(CommentEvent),
+}
+
+impl WebhookEvent {
+    pub fn repository(&self) -> &Repository {
+        match self {
+            WebhookEvent::Issue(event) => &event.repository,
+            WebhookEvent::PR(event) => &event.repository,
+            WebhookEvent::Push(event) => &event.repository,
+            WebhookEvent::Comment(event) => &event.repository,
+        }
+    }
+
+    /// Number of the issue or PR the event is about
+    pub fn number(&self) -> Option<usize> {
+        match self {
+            WebhookEvent::Issue(event) => Some(event.issue.number),
+            WebhookEvent::PR(event) => Some(event.pull_request.number),
+            WebhookEvent::Push(_) => None,
+            WebhookEvent::Comment(event) => Some(event.issue.number),
+        }
+    }
 }
 
 pub mod issue {
     use clap::ValueEnum;
     use serde::{Deserialize, Serialize};
-    use strum_macros::Display;
+    use std::fmt::Display;
+    use strum_macros::AsRefStr;
 
-    use super::{Repository, User};
+    use super::{Label, Repository, User};
 
+    /// Actions as github names them, unknown ones end up in `Other`
     #[derive(
-        Copy,
-        Clone,
-        PartialEq,
-        Eq,
-        PartialOrd,
-        Ord,
-        ValueEnum,
-        Serialize,
-        Deserialize,
-        Debug,
-        Display,
+        Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize, Debug, AsRefStr,
     )]
-    #[serde(rename_all = "lowercase")]
+    #[serde(rename_all = "snake_case")]
     #[strum(serialize_all = "snake_case")]
+    #[value(rename_all = "snake_case")]
     pub enum IssueAction {
         Opened,
         Edited,
@@ -62,13 +87,34 @@ pub mod issue {
         Transferred,
         Milestoned,
         Demilestoned,
+        #[serde(untagged)]
+        #[value(skip)]
+        Other(String),
+    }
+
+    impl Display for IssueAction {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                IssueAction::Other(action) => f.write_str(action),
+                action => f.write_str(action.as_ref()),
+            }
+        }
     }
 
     #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
     pub struct Issue {
         pub id: usize,
+        pub number: usize,
         pub title: String,
         pub url: String,
+        pub html_url: String,
+        pub body: Option<String>,
+        pub state: String,
+        /// Why the issue was closed, `completed` or `not_planned`
+        pub state_reason: Option<String>,
+        pub user: User,
+        pub labels: Vec<Label>,
+        pub assignees: Vec<User>,
     }
 
     #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
@@ -77,31 +123,27 @@ pub mod issue {
         pub sender: User,
         pub repository: Repository,
         pub issue: Issue,
+        /// User who was assigned or unassigned
+        #[serde(default)]
+        pub assignee: Option<User>,
     }
 }
 
 pub mod pr {
     use clap::ValueEnum;
     use serde::{Deserialize, Serialize};
-    use strum_macros::Display;
+    use std::fmt::Display;
+    use strum_macros::AsRefStr;
 
-    use super::{Repository, User};
+    use super::{Label, Repository, User};
 
+    /// Actions as github names them, unknown ones end up in `Other`
     #[derive(
-        Copy,
-        Clone,
-        PartialEq,
-        Eq,
-        PartialOrd,
-        Ord,
-        ValueEnum,
-        Serialize,
-        Deserialize,
-        Debug,
-        Display,
+        Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize, Debug, AsRefStr,
     )]
-    #[serde(rename_all = "lowercase")]
+    #[serde(rename_all = "snake_case")]
     #[strum(serialize_all = "snake_case")]
+    #[value(rename_all = "snake_case")]
     pub enum PRAction {
         Opened,
         Edited,
@@ -113,21 +155,203 @@ pub mod pr {
         ReviewRequestRemoved,
         Labeled,
         Unlabeled,
-        Synchronized,
+        Synchronize,
+        ReadyForReview,
+        ConvertedToDraft,
+        /// Not sent by github, which reports merges as `closed`
+        Merged,
+        #[serde(untagged)]
+        #[value(skip)]
+        Other(String),
+    }
+
+    impl Display for PRAction {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                PRAction::Other(action) => f.write_str(action),
+                action => f.write_str(action.as_ref()),
+            }
+        }
+    }
+
+    impl PRAction {
+        /// Turn the `closed` action of a merged PR into [PRAction::Merged]
+        pub fn resolve(self, pr: &PR) -> Self {
+            match self {
+                PRAction::Closed if pr.merged => PRAction::Merged,
+                action => action,
+            }
+        }
     }
 
     #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
     pub struct PR {
         pub id: usize,
+        pub number: usize,
         pub title: String,
         pub url: String,
+        pub html_url: String,
+        pub body: Option<String>,
+        pub state: String,
+        pub merged: bool,
+        pub merged_by: Option<User>,
+        pub merge_commit_sha: Option<String>,
+        pub draft: bool,
+        pub user: User,
+        pub labels: Vec<Label>,
+        pub assignees: Vec<User>,
+        pub base: Branch,
+        pub head: Branch,
     }
 
     #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+    pub struct Branch {
+        #[serde(rename = "ref")]
+        pub name: String,
+        pub sha: String,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+    #[serde(from = "RawPREvent")]
     pub struct PREvent {
         pub action: PRAction,
         pub sender: User,
         pub repository: Repository,
         pub pull_request: PR,
+        /// User who was assigned or unassigned
+        #[serde(default)]
+        pub assignee: Option<User>,
+        /// User whose review was requested, teams are left out
+        #[serde(default)]
+        pub requested_reviewer: Option<User>,
+    }
+
+    /// Event as sent by github, before merges are told apart from closes
+    #[derive(Deserialize)]
+    struct RawPREvent {
+        action: PRAction,
+        sender: User,
+        repository: Repository,
+        pull_request: PR,
+        #[serde(default)]
+        assignee: Option<User>,
+        #[serde(default)]
+        requested_reviewer: Option<User>,
+    }
+
+    impl From<RawPREvent> for PREvent {
+        fn from(raw: RawPREvent) -> Self {
+            Self {
+                action: raw.action.resolve(&raw.pull_request),
+                sender: raw.sender,
+                repository: raw.repository,
+                pull_request: raw.pull_request,
+                assignee: raw.assignee,
+                requested_reviewer: raw.requested_reviewer,
+            }
+        }
+    }
+}
+
+pub mod comment {
+    use clap::ValueEnum;
+    use serde::{Deserialize, Serialize};
+    use std::fmt::Display;
+    use strum_macros::AsRefStr;
+
+    use super::{issue::Issue, Repository, User};
+
+    /// Actions as github names them, unknown ones end up in `Other`
+    #[derive(
+        Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize, Debug, AsRefStr,
+    )]
+    #[serde(rename_all = "snake_case")]
+    #[strum(serialize_all = "snake_case")]
+    #[value(rename_all = "snake_case")]
+    pub enum CommentAction {
+        Created,
+        Edited,
+        Deleted,
+        #[serde(untagged)]
+        #[value(skip)]
+        Other(String),
+    }
+
+    impl Display for CommentAction {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                CommentAction::Other(action) => f.write_str(action),
+                action => f.write_str(action.as_ref()),
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+    pub struct Comment {
+        pub id: usize,
+        pub html_url: String,
+        pub body: String,
+        pub user: User,
+    }
+
+    /// A comment on an issue or PR, github sends PRs as issues here
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+    pub struct CommentEvent {
+        pub action: CommentAction,
+        pub sender: User,
+        pub repository: Repository,
+        pub issue: Issue,
+        pub comment: Comment,
+    }
+}
+
+pub mod push {
+    use itertools::Itertools;
+    use serde::{Deserialize, Serialize};
+
+    use super::{Repository, User};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+    pub struct Commit {
+        pub id: String,
+        pub message: String,
+        pub added: Vec<String>,
+        pub removed: Vec<String>,
+        pub modified: Vec<String>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+    pub struct PushEvent {
+        #[serde(rename = "ref")]
+        pub git_ref: String,
+        pub sender: User,
+        pub repository: Repository,
+        pub compare: String,
+        pub commits: Vec<Commit>,
+    }
+
+    impl PushEvent {
+        /// Name of the pushed branch, or the full ref for tags
+        pub fn branch(&self) -> &str {
+            self.git_ref
+                .strip_prefix("refs/heads/")
+                .unwrap_or(&self.git_ref)
+        }
+
+        /// All files added, removed or modified by the pushed commits
+        pub fn paths(&self) -> Vec<&str> {
+            self.commits
+                .iter()
+                .flat_map(|commit| {
+                    commit
+                        .added
+                        .iter()
+                        .chain(&commit.removed)
+                        .chain(&commit.modified)
+                })
+                .map(String::as_str)
+                .unique()
+                .collect()
+        }
     }
 }
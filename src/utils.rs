@@ -2,12 +2,20 @@
 
 use anyhow::{Context as _, Result};
 use deltachat::{
-    chat::{send_text_msg, ChatId},
+    blob::BlobObject,
+    chat::{self, send_text_msg, ChatId},
     config::Config,
     context::Context,
+    message::{Message, Viewtype},
 };
 use std::env;
 
+/// Lines of a list sent in one message
+const LINES_PER_MESSAGE: usize = 30;
+
+/// Lists that would take more messages are sent as a text file instead
+const MAX_LIST_MESSAGES: usize = 3;
+
 pub async fn configure_from_env(ctx: &Context) -> Result<()> {
     let addr = env::var("addr")?;
     ctx.set_config(Config::Addr, Some(&addr)).await?;
@@ -21,9 +29,96 @@ pub async fn configure_from_env(ctx: &Context) -> Result<()> {
     Ok(())
 }
 
-pub async fn send_text_to_all(chats: &[ChatId], msg: &str, ctx: &Context) -> anyhow::Result<()> {
-    for chat_id in chats {
-        send_text_msg(ctx, *chat_id, msg.to_string()).await?;
+/// The messages a list is sent as
+#[derive(Debug, PartialEq, Eq)]
+enum ListMessages {
+    Texts(Vec<String>),
+    File { text: String, content: String },
+}
+
+/// Split a list below a heading across messages, or into a file if it is long
+fn list_messages(heading: &str, lines: &[String]) -> ListMessages {
+    if lines.len() > LINES_PER_MESSAGE * MAX_LIST_MESSAGES {
+        return ListMessages::File {
+            text: format!("{heading} ({} entries)", lines.len()),
+            content: lines.join("\n"),
+        };
+    }
+    let texts = lines
+        .chunks(LINES_PER_MESSAGE)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let text = chunk.join("\n");
+            if i == 0 {
+                format!("{heading}\n{text}")
+            } else {
+                text
+            }
+        })
+        .collect();
+    ListMessages::Texts(texts)
+}
+
+/// Send a list below a heading, split across messages or attached as a file if it is long
+pub async fn send_list(
+    ctx: &Context,
+    chat_id: ChatId,
+    heading: &str,
+    lines: &[String],
+) -> Result<()> {
+    match list_messages(heading, lines) {
+        ListMessages::File { text, content } => {
+            let blob = BlobObject::create(ctx, "list.txt", content.as_bytes()).await?;
+            let mut msg = Message::new(Viewtype::File);
+            msg.set_text(Some(text));
+            msg.set_file(blob.as_name(), Some("text/plain"));
+            chat::send_msg(ctx, chat_id, &mut msg).await?;
+        }
+        ListMessages::Texts(texts) => {
+            for text in texts {
+                send_text_msg(ctx, chat_id, text).await?;
+            }
+        }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(count: usize) -> Vec<String> {
+        (1..=count).map(|i| format!("#{i}")).collect()
+    }
+
+    #[test]
+    fn test_short_list() {
+        assert_eq!(
+            list_messages("Open issues:", &lines(2)),
+            ListMessages::Texts(vec!["Open issues:\n#1\n#2".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_split_list() {
+        let ListMessages::Texts(texts) = list_messages("Open issues:", &lines(61)) else {
+            panic!("list should be sent as text");
+        };
+        assert_eq!(texts.len(), 3);
+        assert!(texts[0].starts_with("Open issues:\n#1\n"));
+        assert!(texts[1].starts_with("#31\n"));
+        assert_eq!(texts[2], "#61");
+    }
+
+    #[test]
+    fn test_list_file() {
+        let lines = lines(LINES_PER_MESSAGE * MAX_LIST_MESSAGES + 1);
+        assert_eq!(
+            list_messages("Open issues:", &lines),
+            ListMessages::File {
+                text: "Open issues: (91 entries)".to_string(),
+                content: lines.join("\n"),
+            }
+        );
+    }
+}
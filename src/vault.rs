@@ -0,0 +1,86 @@
+//! Encryption of secrets like tokens and api keys before they are stored
+//!
+//! The key is derived from a passphrase the operator sets in the environment,
+//! so a copy of the database alone doesn't reveal the secrets.
+
+use anyhow::{anyhow, bail, Result};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Nonce,
+};
+use sha2::{Digest, Sha256};
+
+/// Marks stored values that are encrypted, older ones were stored in plaintext
+const PREFIX: &str = "sealed:";
+
+/// Bytes of the nonce in front of every sealed value
+const NONCE_LENGTH: usize = 12;
+
+pub struct Vault {
+    cipher: ChaCha20Poly1305,
+}
+
+impl Vault {
+    /// Create a vault with the key derived from a passphrase
+    pub fn new(passphrase: &str) -> Self {
+        let key = Sha256::digest(passphrase.as_bytes());
+        Self {
+            cipher: ChaCha20Poly1305::new(&key),
+        }
+    }
+
+    /// Encrypt a secret for storing it
+    pub fn seal(&self, secret: &str) -> Result<String> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = self
+            .cipher
+            .encrypt(&nonce, secret.as_bytes())
+            .map_err(|_| anyhow!("failed to encrypt a secret"))?;
+        Ok(format!(
+            "{PREFIX}{}{}",
+            hex::encode(nonce),
+            hex::encode(sealed)
+        ))
+    }
+
+    /// Decrypt a stored secret
+    pub fn open(&self, stored: &str) -> Result<String> {
+        let Some(sealed) = stored.strip_prefix(PREFIX) else {
+            bail!("the secret isn't encrypted");
+        };
+        let sealed = hex::decode(sealed)?;
+        if sealed.len() < NONCE_LENGTH {
+            bail!("the secret is too short");
+        }
+        let (nonce, sealed) = sealed.split_at(NONCE_LENGTH);
+        let secret = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), sealed)
+            .map_err(|_| anyhow!("failed to decrypt a secret, was `db_key` changed?"))?;
+        Ok(String::from_utf8(secret)?)
+    }
+
+    /// Check whether a stored value is encrypted
+    pub fn is_sealed(stored: &str) -> bool {
+        stored.starts_with(PREFIX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vault() {
+        let vault = Vault::new("passphrase");
+        let sealed = vault.seal("ghp_xyz").unwrap();
+        assert!(Vault::is_sealed(&sealed));
+        assert!(!sealed.contains("ghp_xyz"));
+        assert_ne!(sealed, vault.seal("ghp_xyz").unwrap());
+        assert_eq!(vault.open(&sealed).unwrap(), "ghp_xyz");
+
+        assert!(Vault::new("other").open(&sealed).is_err());
+        assert!(vault.open("ghp_xyz").is_err());
+        assert!(vault.open("sealed:00").is_err());
+    }
+}